@@ -22,7 +22,13 @@ async fn main() -> anyhow::Result<()> {
     // c.set_color(Color::from_hex("FF0000")?, Effect::Smooth(500))?;
 
     let resp = c
-        .get_props_map(&["power", "bright", "ct", "rgb", "color_mode"])
+        .get_props_map(&[
+            Property::Power,
+            Property::Bright,
+            Property::Ct,
+            Property::Rgb,
+            Property::ColorMode,
+        ])
         .await?;
     println!("Props: {:?}", resp);
 
@@ -9,19 +9,40 @@ axios.get("config.json").then(res => {
     const bulb = config.bulbs[name]
     initialState.bulbs[name] = {
       name: name,
+      /* The name from the config, preferred over the one stored on
+         the bulb.  Anonymous bulbs have none. */
+      label: bulb.addr ? name : undefined,
       addr: bulb.addr || name,
+      /* Preferred over the address, which might change. */
+      id: bulb.id,
+      channel: bulb.channel || "main",
       isRGB: bulb.rgb || false,
       linked: bulb.linked || [],
       power: undefined,
       brightness: undefined,
       temperature: undefined,
-      color: undefined
+      color: undefined,
+      timer: undefined,
+      /* The supported controls, if known to the server. */
+      capabilities: undefined,
+      error: undefined
+    }
+  }
+
+  const errorMessage = err => {
+    if (err.response && err.response.data) {
+      return err.response.data
+    } else {
+      return err.message
     }
   }
 
   const store = new Vuex.Store({
     state: initialState,
     mutations: {
+      label(state, { bulb, label }) {
+        state.bulbs[bulb].label = label
+      },
       power(state, { bulb, power }) {
         state.bulbs[bulb].power = power
       },
@@ -33,18 +54,43 @@ axios.get("config.json").then(res => {
       },
       color(state, { bulb, color }) {
         state.bulbs[bulb].color = color
+      },
+      capabilities(state, { bulb, capabilities }) {
+        state.bulbs[bulb].capabilities = capabilities
+      },
+      timer(state, { bulb, timer }) {
+        state.bulbs[bulb].timer = timer
+      },
+      error(state, { bulb, error }) {
+        state.bulbs[bulb].error = error
       }
     },
     actions: {
+      /* Run a bulb request, recording its failure for display. */
+      request(context, { bulb, url, method }) {
+        return axios({ method: method || 'post', url }).then(res => {
+          context.commit('error', { bulb, error: undefined })
+          return true
+        }).catch(err => {
+          /* Superseded by a newer request, not worth reporting. */
+          if (!(err.response && err.response.status === 409)) {
+            context.commit('error', { bulb, error: errorMessage(err) })
+          }
+          return false
+        })
+      },
       setPower(context, { bulb, power }) {
         switch (power) {
         case true:
         case false:
-          const addr = context.getters.addr(bulb)
-          return axios.post(
-            (power ? "on" : "off") + "?bulb=" + addr
-          ).then(res => {
-            context.commit('power', { bulb, power })
+          const query = context.getters.query(bulb)
+          return context.dispatch('request', {
+            bulb,
+            url: (power ? "on" : "off") + query
+          }).then(ok => {
+            if (ok) {
+              context.commit('power', { bulb, power })
+            }
           })
         default:
           return context.commit('power', { bulb, power: undefined })
@@ -54,40 +100,84 @@ axios.get("config.json").then(res => {
         if (context.getters.power(bulb) !== true) {
           context.dispatch('setPower', { bulb, power: true })
         }
-        const addr = context.getters.addr(bulb)
-        return axios.post(
-          "brightness?bulb=" + addr + "&brightness=" + brightness
-        ).then(() => {
-          context.commit('brightness', { bulb, brightness })
+        const query = context.getters.query(bulb)
+        return context.dispatch('request', {
+          bulb,
+          url: "brightness" + query + "&brightness=" + brightness
+        }).then(ok => {
+          if (ok) {
+            context.commit('brightness', { bulb, brightness })
+          }
         })
       },
       setTemperature(context, { bulb, temperature }) {
+        /* Linked bulbs may support narrower ranges. */
+        const range = context.getters.temperatureRange(bulb)
+        temperature = Math.min(Math.max(temperature, range.min), range.max)
         if (context.getters.power(bulb) !== true) {
           context.dispatch('setPower', { bulb, power: true })
         }
-        const addr = context.getters.addr(bulb)
-        return axios.post(
-          "temperature?bulb=" + addr + "&temperature=" + temperature
-        ).then(() => {
-          context.commit('temperature', { bulb, temperature })
+        const query = context.getters.query(bulb)
+        return context.dispatch('request', {
+          bulb,
+          url: "temperature" + query + "&temperature=" + temperature
+        }).then(ok => {
+          if (ok) {
+            context.commit('temperature', { bulb, temperature })
+          }
+        })
+      },
+      /* Turn the bulb off after the given number of minutes. */
+      setTimer(context, { bulb, minutes }) {
+        const query = context.getters.query(bulb)
+        return context.dispatch('request', {
+          bulb,
+          url: "timer" + query + "&minutes=" + minutes
+        }).then(ok => {
+          if (ok) {
+            context.commit('timer', { bulb, timer: minutes })
+          }
+        })
+      },
+      cancelTimer(context, { bulb }) {
+        const query = context.getters.query(bulb)
+        return context.dispatch('request', {
+          bulb,
+          url: "timer" + query,
+          method: 'delete'
+        }).then(ok => {
+          if (ok) {
+            context.commit('timer', { bulb, timer: undefined })
+          }
         })
       },
       setColor(context, { bulb, color }) {
         if (context.getters.power(bulb) !== true) {
           context.dispatch('setPower', { bulb, power: true })
         }
-        const addr = context.getters.addr(bulb)
-        return axios.post(
-          "color?bulb=" + addr + "&rgb=" + color.substr(1)
-        ).then(() => {
-          context.commit('color', { bulb, color })
+        const query = context.getters.query(bulb)
+        return context.dispatch('request', {
+          bulb,
+          url: "color" + query + "&color=" + encodeURIComponent(color)
+        }).then(ok => {
+          if (ok) {
+            context.commit('color', { bulb, color })
+          }
         })
       }
     },
     getters: {
+      label: state => bulb => {
+        return state.bulbs[bulb].label || bulb
+      },
       addr: state => bulb => {
         return state.bulbs[bulb].addr
       },
+      /* The query string selecting the bulb and its light. */
+      query: state => bulb => {
+        const { id, addr, channel } = state.bulbs[bulb]
+        return "?bulb=" + (id || addr) + "&channel=" + channel
+      },
       power: state => bulb => {
         return state.bulbs[bulb].power
       },
@@ -100,8 +190,30 @@ axios.get("config.json").then(res => {
       color: state => bulb => {
         return state.bulbs[bulb].color
       },
+      timer: state => bulb => {
+        return state.bulbs[bulb].timer
+      },
       isRGB: state => bulb => {
+        const capabilities = state.bulbs[bulb].capabilities
+        if (capabilities) {
+          return capabilities.rgb
+        }
         return state.bulbs[bulb].isRGB
+      },
+      temperatureRange: state => bulb => {
+        const capabilities = state.bulbs[bulb].capabilities
+        if (capabilities && capabilities.temperature_range) {
+          return capabilities.temperature_range
+        }
+        return { min: 1700, max: 6500 }
+      },
+      /* Unknown capabilities allow every control. */
+      supports: state => (bulb, control) => {
+        const capabilities = state.bulbs[bulb].capabilities
+        return !capabilities || capabilities[control]
+      },
+      error: state => bulb => {
+        return state.bulbs[bulb].error
       }
     }
   })
@@ -130,6 +242,15 @@ axios.get("config.json").then(res => {
           )
         })
       },
+      supports(control) {
+        return this.$store.getters.supports(this.name, control)
+      },
+      setTimer(minutes) {
+        this.$store.dispatch('setTimer', { bulb: this.name, minutes })
+      },
+      cancelTimer() {
+        this.$store.dispatch('cancelTimer', { bulb: this.name })
+      },
       setBrightness(newValue) {
         this.$store.dispatch(
           'setBrightness', { bulb: this.name, brightness: newValue }
@@ -177,6 +298,9 @@ axios.get("config.json").then(res => {
       }
     },
     computed: {
+      label() {
+        return this.$store.getters.label(this.name)
+      },
       power() {
         return this.$store.getters.power(this.name)
       },
@@ -216,37 +340,47 @@ axios.get("config.json").then(res => {
           this.localColor = newValue
         }
       },
+      timer() {
+        return this.$store.getters.timer(this.name)
+      },
+      temperatureRange() {
+        return this.$store.getters.temperatureRange(this.name)
+      },
       isRGB() {
         return this.$store.getters.isRGB(this.name)
+      },
+      error() {
+        return this.$store.getters.error(this.name)
       }
     },
     mounted() {
-      axios.get("info?bulb=" + this.addr).then(res => {
+      axios.get("info" + this.$store.getters.query(this.name)).then(res => {
         const info = res.data
+        if (info.name && this.$store.state.bulbs[this.name].label === undefined) {
+          this.$store.commit('label', {
+            bulb: this.name,
+            label: info.name
+          })
+        }
         this.$store.commit('brightness', {
           bulb: this.name,
-          brightness: info.bright
+          brightness: info.brightness
         })
         this.$store.commit('temperature', {
           bulb: this.name,
-          temperature: info.ct
+          temperature: info.temperature
         })
         this.$store.commit('power', {
           bulb: this.name,
-          power: info.power === "on"
+          power: info.power
         })
 
-        let toRGBString = base10 => {
-          let rgb = parseInt(base10, 10).toString(16)
-          while (rgb.length < 6) {
-            rgb = "0" + rgb
-          }
-          return "#" + rgb
+        if (info.color !== null) {
+          this.$store.commit('color', {
+            bulb: this.name,
+            color: info.color
+          })
         }
-        this.$store.commit('color', {
-          bulb: this.name,
-          color: toRGBString(info.rgb)
-        })
 
         this.linked = this.$store.state.bulbs[this.name].linked.map(link => {
           if (typeof link === 'string') {
@@ -261,6 +395,31 @@ axios.get("config.json").then(res => {
             }
           }
         })
+      }).catch(err => {
+        this.$store.commit('error', {
+          bulb: this.name,
+          error: errorMessage(err)
+        })
+      })
+
+      axios.get("capabilities" + this.$store.getters.query(this.name)).then(res => {
+        this.$store.commit('capabilities', {
+          bulb: this.name,
+          capabilities: res.data || undefined
+        })
+      }).catch(() => {
+        /* Already reported by the info request. */
+      })
+
+      axios.get("timer" + this.$store.getters.query(this.name)).then(res => {
+        if (res.data) {
+          this.$store.commit('timer', {
+            bulb: this.name,
+            timer: res.data.minutes
+          })
+        }
+      }).catch(() => {
+        /* Already reported by the info request. */
       })
     }
   })
@@ -1,16 +1,52 @@
 use log::info;
+use std::collections::BTreeMap;
 use std::io;
 use std::net::{AddrParseError, IpAddr, SocketAddr};
 use std::str::FromStr;
 use tokio::net::TcpStream;
+use tokio::time;
 
-use crate::BulbConnection;
+use crate::{BulbConnection, BulbError, Capabilities, TimeoutKind, Timeouts};
 
 pub const PORT: u16 = 55443;
 
-#[derive(Debug)]
+/// Bulb details advertised in the discovery replies.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BulbInfo {
+    pub id: String,
+    pub model: String,
+    pub fw_ver: String,
+    pub support: Vec<String>,
+    /// The current state, e.g. `power`, `bright` or `rgb`.
+    pub props: BTreeMap<String, String>,
+}
+
+impl BulbInfo {
+    pub fn name(&self) -> Option<&str> {
+        self.props
+            .get("name")
+            .map(String::as_str)
+            .filter(|name| !name.is_empty())
+    }
+
+    /// The advertised `support` list, or the one known for the model
+    /// if it's missing.
+    pub fn capabilities(&self) -> Option<Capabilities> {
+        if self.support.is_empty() {
+            Capabilities::for_model(&self.model)
+        } else {
+            Some(Capabilities::from_support(&self.support).with_temperatures_of(&self.model))
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
 pub struct Bulb {
     addr: SocketAddr,
+    info: Option<BulbInfo>,
+    /// `None` if unknown, allowing any command.
+    capabilities: Option<Capabilities>,
+    timeouts: Timeouts,
 }
 
 impl FromStr for Bulb {
@@ -21,16 +57,72 @@ impl FromStr for Bulb {
     }
 }
 
+impl From<SocketAddr> for Bulb {
+    fn from(addr: SocketAddr) -> Self {
+        Bulb {
+            addr,
+            info: None,
+            capabilities: None,
+            timeouts: Timeouts::default(),
+        }
+    }
+}
+
 impl Bulb {
     pub fn new(addr: IpAddr) -> Self {
+        Bulb::from(SocketAddr::new(addr, PORT))
+    }
+
+    pub fn with_info(addr: SocketAddr, info: BulbInfo) -> Self {
+        Bulb {
+            capabilities: info.capabilities(),
+            info: Some(info),
+            ..Bulb::from(addr)
+        }
+    }
+
+    /// Use these timeouts when connecting and for the commands sent
+    /// over the resulting connections.
+    pub fn with_timeouts(self, timeouts: Timeouts) -> Self {
+        Bulb { timeouts, ..self }
+    }
+
+    /// Refuse sending the commands unsupported by the bulb, e.g. when
+    /// its model is known from the config.
+    pub fn with_capabilities(self, capabilities: Capabilities) -> Self {
         Bulb {
-            addr: SocketAddr::new(addr, PORT),
+            capabilities: Some(capabilities),
+            ..self
         }
     }
 
-    pub async fn connect(&self) -> io::Result<BulbConnection> {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The details from discovery, if the bulb was discovered.
+    pub fn info(&self) -> Option<&BulbInfo> {
+        self.info.as_ref()
+    }
+
+    pub fn capabilities(&self) -> Option<&Capabilities> {
+        self.capabilities.as_ref()
+    }
+
+    pub async fn connect(&self) -> Result<BulbConnection, BulbError> {
         info!("Connecting to: {}", self.addr);
-        let connection = BulbConnection::new(TcpStream::connect(&self.addr).await?)?;
+        let stream = time::timeout(self.timeouts.connect, TcpStream::connect(&self.addr))
+            .await
+            .map_err(|_| BulbError::Timeout(TimeoutKind::Connect))?
+            .map_err(|err| match err.kind() {
+                io::ErrorKind::TimedOut => BulbError::Timeout(TimeoutKind::Connect),
+                _ => BulbError::Connect(err),
+            })?;
+        let mut connection = BulbConnection::new(stream)?;
+        connection.set_timeouts(self.timeouts);
+        if let Some(capabilities) = &self.capabilities {
+            connection.set_capabilities(capabilities.clone());
+        }
         info!("Connected to: {}", self.addr);
         Ok(connection)
     }
@@ -1,13 +1,23 @@
 use log::{info, warn};
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
 use std::io;
+use std::sync::atomic::{AtomicU16, Ordering};
+use std::sync::{Arc, Mutex};
+use thiserror::Error;
 use tokio::io::BufReader;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::net::TcpStream;
+use tokio::sync::{broadcast, oneshot, Mutex as AsyncMutex};
+use tokio::task::JoinHandle;
+use tokio::time::{self, Duration};
 
-use crate::params::{Brightness, Color, Effect, Percentage, Temperature};
+use crate::{
+    BulbCommands, Capabilities, FlowError, Property, PropertyValue, RangeError, RateLimiter,
+};
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Command {
@@ -25,148 +35,386 @@ pub struct Response {
     pub error: Option<Map<String, Value>>,
 }
 
+/// The props changed on the bulb, pushed by it on its own, e.g. after
+/// a command from another client.
+#[derive(Serialize, Clone, Debug, PartialEq)]
+pub struct Notification {
+    pub props: BTreeMap<Property, PropertyValue>,
+}
+
+impl Notification {
+    /// Decode a `props` message, skipping the values not understood.
+    fn from_message(message: &Value) -> Option<Self> {
+        let method = message.get("method").and_then(Value::as_str);
+        if method != Some("props") {
+            warn!("Unknown notification, ignoring: {}", message);
+            return None;
+        }
+        let Some(params) = message.get("params").and_then(Value::as_object) else {
+            warn!("Notification without props, ignoring: {}", message);
+            return None;
+        };
+        let mut props = BTreeMap::new();
+        for (name, value) in params {
+            let Ok(prop) = name.parse::<Property>() else {
+                warn!("Unknown prop in a notification, ignoring: {}", name);
+                continue;
+            };
+            // Some values are pushed as numbers, unlike in `get_prop`.
+            let value = match value {
+                Value::String(value) => value.clone(),
+                Value::Number(value) => value.to_string(),
+                value => {
+                    warn!("Invalid {} value, ignoring: {}", prop, value);
+                    continue;
+                }
+            };
+            match prop.decode(&value) {
+                Ok(value) => {
+                    props.insert(prop, value);
+                }
+                Err(err) => warn!("{}, ignoring", err),
+            }
+        }
+        Some(Self { props })
+    }
+}
+
+/// The limits for the individual steps of talking to a bulb.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Timeouts {
+    pub connect: Duration,
+    pub write: Duration,
+    /// The time to wait for the reply to a command.
+    pub response: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            connect: Duration::from_secs(5),
+            write: Duration::from_secs(5),
+            response: Duration::from_secs(5),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TimeoutKind {
+    Connect,
+    Write,
+    Response,
+}
+
+impl fmt::Display for TimeoutKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeoutKind::Connect => write!(f, "connecting"),
+            TimeoutKind::Write => write!(f, "sending the command"),
+            TimeoutKind::Response => write!(f, "waiting for the response"),
+        }
+    }
+}
+
+#[derive(Error, Debug)]
+pub enum BulbError {
+    #[error("Unable to connect: {}", .0)]
+    Connect(#[source] io::Error),
+    #[error("Timed out {}", .0)]
+    Timeout(TimeoutKind),
+    #[error("Connection closed by the bulb")]
+    Disconnected,
+    #[error("Connection error: {}", .0)]
+    Io(#[from] io::Error),
+    #[error("Unable to parse the bulb message: {}", .0)]
+    Protocol(#[from] serde_json::Error),
+    #[error("Bulb error {}: {}", .code, .message)]
+    Bulb { code: i64, message: String },
+    #[error("Unexpected result: {}", .0)]
+    UnexpectedResult(String),
+    #[error("Superseded by a newer command")]
+    Superseded,
+    #[error("Not supported by the bulb: {}", .0)]
+    Unsupported(String),
+    #[error("Outside of the bulb's range: {}", .0)]
+    OutOfRange(#[from] RangeError<u16>),
+    #[error("Invalid color flow: {}", .0)]
+    InvalidFlow(#[from] FlowError),
+}
+
+impl BulbError {
+    /// Whether the connection is no longer usable and needs to be
+    /// reestablished.
+    pub fn is_disconnect(&self) -> bool {
+        match self {
+            BulbError::Disconnected => true,
+            BulbError::Io(err) => matches!(
+                err.kind(),
+                io::ErrorKind::BrokenPipe
+                    | io::ErrorKind::ConnectionReset
+                    | io::ErrorKind::ConnectionAborted
+                    | io::ErrorKind::UnexpectedEof
+            ),
+            _ => false,
+        }
+    }
+
+    /// Convert the `error` object of a [`Response`].
+    pub fn from_response_error(error: &Map<String, Value>) -> Self {
+        BulbError::Bulb {
+            code: error
+                .get("code")
+                .and_then(Value::as_i64)
+                .unwrap_or_default(),
+            message: error
+                .get("message")
+                .and_then(Value::as_str)
+                .unwrap_or_default()
+                .to_owned(),
+        }
+    }
+}
+
+/// The callers waiting for the responses, keyed by the command id.
+///
+/// Set to `None` once the connection gets closed.
+type PendingResponses =
+    Arc<Mutex<Option<HashMap<u16, oneshot::Sender<Result<Response, BulbError>>>>>>;
+
+/// The socket state shared by all the clones of a [`BulbConnection`].
 #[derive(Debug)]
+struct Shared {
+    writer: AsyncMutex<OwnedWriteHalf>,
+    reader: JoinHandle<()>,
+    pending: PendingResponses,
+    notifications: broadcast::Sender<Notification>,
+    last_command_id: AtomicU16,
+}
+
+impl Drop for Shared {
+    fn drop(&mut self) {
+        self.reader.abort();
+    }
+}
+
+/// A connection to a bulb.
+///
+/// The clones share the underlying socket and can send the commands
+/// concurrently, without waiting for the previous responses.
+#[derive(Clone, Debug)]
 pub struct BulbConnection {
-    stream: TcpStream,
-    last_command_id: u16,
+    shared: Arc<Shared>,
+    timeouts: Timeouts,
+    rate_limiter: Arc<RateLimiter>,
+    capabilities: Option<Arc<Capabilities>>,
 }
 
 impl BulbConnection {
+    /// Wrap the stream, spawning a task reading from it.
     pub fn new(stream: TcpStream) -> io::Result<Self> {
+        let (reader, writer) = stream.into_split();
+        let pending: PendingResponses = Arc::new(Mutex::new(Some(HashMap::new())));
+        let (notifications, _) = broadcast::channel(16);
+        let reader = tokio::spawn(read_messages(
+            reader,
+            pending.clone(),
+            notifications.clone(),
+        ));
         Ok(Self {
-            stream,
-            last_command_id: 0,
+            shared: Arc::new(Shared {
+                writer: AsyncMutex::new(writer),
+                reader,
+                pending,
+                notifications,
+                last_command_id: AtomicU16::new(0),
+            }),
+            timeouts: Timeouts::default(),
+            rate_limiter: Arc::default(),
+            capabilities: None,
         })
     }
 
-    fn new_command(&mut self, method: &str, params: Vec<Value>) -> Command {
-        self.last_command_id += 1;
+    pub fn rate_limiter(&self) -> &Arc<RateLimiter> {
+        &self.rate_limiter
+    }
+
+    /// Share the command quota with other connections to the same bulb.
+    pub fn set_rate_limiter(&mut self, rate_limiter: Arc<RateLimiter>) {
+        self.rate_limiter = rate_limiter;
+    }
+
+    pub fn timeouts(&self) -> Timeouts {
+        self.timeouts
+    }
+
+    /// Change the timeouts for the subsequent commands sent through
+    /// this handle.
+    pub fn set_timeouts(&mut self, timeouts: Timeouts) {
+        self.timeouts = timeouts;
+    }
+
+    /// Fail the unsupported commands without sending them.
+    pub fn set_capabilities(&mut self, capabilities: Capabilities) {
+        self.capabilities = Some(Arc::new(capabilities));
+    }
+
+    /// Receive the notifications pushed by the bulb from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<Notification> {
+        self.shared.notifications.subscribe()
+    }
+
+    /// Whether both are clones of the same connection.
+    pub(crate) fn shares_socket(&self, other: &BulbConnection) -> bool {
+        Arc::ptr_eq(&self.shared, &other.shared)
+    }
+
+    /// Whether the bulb has closed or reset the connection.
+    pub fn is_closed(&self) -> bool {
+        self.shared.reader.is_finished()
+    }
+
+    fn new_command(&self, method: &str, params: Vec<Value>) -> Command {
+        let id = self.shared.last_command_id.fetch_add(1, Ordering::Relaxed);
         Command {
-            id: self.last_command_id,
+            id: id.wrapping_add(1),
             method: method.to_owned(),
             params,
         }
     }
 
-    async fn call(&mut self, command: Command) -> io::Result<Response> {
+    async fn call(&self, command: Command) -> Result<Response, BulbError> {
+        self.rate_limiter.acquire(&command.method).await?;
+
         let payload = serde_json::to_string(&command)?;
         // Additional space to align the output with "Received".
         info!("Sending:  {}", payload);
         let payload = payload + "\r\n";
-        self.stream.write_all(payload.as_bytes()).await?;
-
-        loop {
-            let mut response = String::new();
-            let mut reader = BufReader::new(&mut self.stream);
-            reader.read_line(&mut response).await?;
-            let response = response.trim_end();
-            info!("Received: {}", response);
-
-            match serde_json::from_str::<Response>(response) {
-                Ok(response) => {
-                    info!("Parsed as: {:?}", response);
-                    if response.id == command.id {
-                        return Ok(response);
-                    } else {
-                        warn!("Not matching id, ignoring: {}", response.id);
-                    }
-                }
-                Err(err) => {
-                    warn!("Unable to parse, ignoring: {}", err);
-                }
+
+        let (sender, receiver) = oneshot::channel();
+        match self.shared.pending.lock().unwrap().as_mut() {
+            Some(pending) => pending.insert(command.id, sender),
+            None => return Err(BulbError::Disconnected),
+        };
+
+        let written = time::timeout(self.timeouts.write, async {
+            self.shared
+                .writer
+                .lock()
+                .await
+                .write_all(payload.as_bytes())
+                .await
+        })
+        .await
+        .map_err(|_| BulbError::Timeout(TimeoutKind::Write))
+        .and_then(|written| Ok(written?));
+        if let Err(err) = written {
+            self.forget(command.id);
+            return Err(err);
+        }
+
+        let response = match time::timeout(self.timeouts.response, receiver).await {
+            Ok(Ok(response)) => response?,
+            // The reader has finished without failing this command.
+            Ok(Err(_)) => return Err(BulbError::Disconnected),
+            Err(_) => {
+                self.forget(command.id);
+                return Err(BulbError::Timeout(TimeoutKind::Response));
             }
+        };
+        if let Some(error) = &response.error {
+            return Err(BulbError::from_response_error(error));
+        }
+        Ok(response)
+    }
+
+    /// Stop waiting for the response to a command.
+    fn forget(&self, id: u16) {
+        if let Some(pending) = self.shared.pending.lock().unwrap().as_mut() {
+            pending.remove(&id);
         }
     }
+}
 
-    pub async fn set_power(&mut self, state: bool, effect: Effect) -> io::Result<Response> {
-        let state = match state {
-            true => "on",
-            false => "off",
+/// Route the messages from the bulb to either the callers waiting for
+/// the responses or to the notification subscribers.
+async fn read_messages(
+    reader: OwnedReadHalf,
+    pending: PendingResponses,
+    notifications: broadcast::Sender<Notification>,
+) {
+    let mut lines = BufReader::new(reader).lines();
+    let closed_by = loop {
+        let line = match lines.next_line().await {
+            Ok(Some(line)) => line,
+            Ok(None) => break None,
+            Err(err) => break Some(err),
         };
+        let line = line.trim_end();
+        info!("Received: {}", line);
 
-        let command = self.new_command(
-            "set_power",
-            vec![
-                state.into(),
-                effect.effect().into(),
-                effect.duration().into(),
-            ],
-        );
+        let message: Value = match serde_json::from_str(line) {
+            Ok(message) => message,
+            Err(err) => {
+                // A response lost in it makes its command time out.
+                warn!("Unable to parse, ignoring: {}", err);
+                continue;
+            }
+        };
 
-        self.call(command).await
-    }
+        if message.get("method").is_some() {
+            if let Some(notification) = Notification::from_message(&message) {
+                info!("Parsed as: {:?}", notification);
+                // Nobody being subscribed is fine.
+                let _ = notifications.send(notification);
+            }
+            continue;
+        }
 
-    pub async fn set_brightness(
-        &mut self,
-        Brightness(brightness): Brightness,
-        effect: Effect,
-    ) -> io::Result<Response> {
-        let command = self.new_command(
-            "set_bright",
-            vec![
-                brightness.into(),
-                effect.effect().into(),
-                effect.duration().into(),
-            ],
-        );
-        self.call(command).await
-    }
+        match serde_json::from_value::<Response>(message) {
+            Ok(response) => {
+                info!("Parsed as: {:?}", response);
+                let sender = pending
+                    .lock()
+                    .unwrap()
+                    .as_mut()
+                    .and_then(|pending| pending.remove(&response.id));
+                match sender {
+                    Some(sender) => {
+                        let _ = sender.send(Ok(response));
+                    }
+                    None => {
+                        warn!("Not matching id, ignoring: {}", response.id);
+                    }
+                }
+            }
+            Err(err) => {
+                warn!("Unable to parse, ignoring: {}", err);
+            }
+        }
+    };
 
-    pub async fn adjust_brightness(
-        &mut self,
-        Percentage(percentage): Percentage,
-        duration: u16,
-    ) -> io::Result<Response> {
-        let command = self.new_command("adjust_bright", vec![percentage.into(), duration.into()]);
-        self.call(command).await
+    let closed_error = || match &closed_by {
+        None => BulbError::Disconnected,
+        Some(err) => BulbError::Io(io::Error::new(err.kind(), err.to_string())),
+    };
+    let pending = pending.lock().unwrap().take().unwrap_or_default();
+    for (_, sender) in pending {
+        let _ = sender.send(Err(closed_error()));
     }
+}
 
-    pub async fn set_temperature(
-        &mut self,
-        Temperature(temperature): Temperature,
-        effect: Effect,
-    ) -> io::Result<Response> {
-        let command = self.new_command(
-            "set_ct_abx",
-            vec![
-                temperature.into(),
-                effect.effect().into(),
-                effect.duration().into(),
-            ],
-        );
-        self.call(command).await
+impl BulbCommands for BulbConnection {
+    fn capabilities(&self) -> Option<&Capabilities> {
+        self.capabilities.as_deref()
     }
 
-    pub async fn set_color(&mut self, Color(color): Color, effect: Effect) -> io::Result<Response> {
-        let command = self.new_command(
-            "set_rgb",
-            vec![
-                color.into(),
-                effect.effect().into(),
-                effect.duration().into(),
-            ],
-        );
+    async fn request(&mut self, method: &str, params: Vec<Value>) -> Result<Response, BulbError> {
+        if let Some(capabilities) = &self.capabilities {
+            if !capabilities.supports(method) {
+                return Err(BulbError::Unsupported(method.to_owned()));
+            }
+        }
+        let command = self.new_command(method, params);
         self.call(command).await
     }
-
-    pub async fn get_props(&mut self, props: &[&str]) -> io::Result<Vec<String>> {
-        let props = props.iter().map(|x| Value::from(*x)).collect();
-        let command = self.new_command("get_prop", props);
-        let response = self.call(command).await?;
-        let values: Vec<String> = response
-            .result
-            .expect("No results in the response")
-            .iter()
-            .map(|x| x.as_str().expect("Got an invalid prop value").to_owned())
-            .collect();
-        Ok(values)
-    }
-
-    pub async fn get_props_map<'a>(
-        &mut self,
-        props: &[&'a str],
-    ) -> io::Result<BTreeMap<&'a str, String>> {
-        let values = self.get_props(props).await?;
-        Ok(BTreeMap::from_iter(props.iter().copied().zip(values)))
-    }
 }
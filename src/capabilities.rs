@@ -0,0 +1,376 @@
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+use crate::params::{ColorFlow, FlowStep, RangeError, Temperature};
+use crate::Channel;
+
+/// The methods every bulb supports.
+const BASIC_METHODS: &[&str] = &[
+    "get_prop",
+    "set_default",
+    "set_power",
+    "toggle",
+    "set_bright",
+    "adjust_bright",
+    "set_adjust",
+    "start_cf",
+    "stop_cf",
+    "set_scene",
+    "cron_add",
+    "cron_get",
+    "cron_del",
+    "set_name",
+];
+
+const TEMPERATURE_METHODS: &[&str] = &["set_ct_abx", "adjust_ct"];
+
+const COLOR_METHODS: &[&str] = &[
+    "set_ct_abx",
+    "adjust_ct",
+    "set_rgb",
+    "set_hsv",
+    "adjust_color",
+    "set_music",
+];
+
+const BACKGROUND_METHODS: &[&str] = &[
+    "dev_toggle",
+    "bg_set_power",
+    "bg_toggle",
+    "bg_set_bright",
+    "bg_adjust_bright",
+    "bg_set_ct_abx",
+    "bg_adjust_ct",
+    "bg_set_rgb",
+    "bg_set_hsv",
+    "bg_adjust_color",
+    "bg_set_adjust",
+    "bg_start_cf",
+    "bg_stop_cf",
+    "bg_set_scene",
+    "bg_set_default",
+];
+
+/// The color temperatures a bulb accepts, in Kelvins.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct TemperatureRange {
+    pub min: u16,
+    pub max: u16,
+}
+
+impl TemperatureRange {
+    pub fn check(&self, temperature: Temperature) -> Result<Temperature, RangeError<u16>> {
+        let Temperature(value) = temperature;
+        if (self.min..=self.max).contains(&value) {
+            Ok(temperature)
+        } else {
+            Err(RangeError::new(value, self.min, self.max))
+        }
+    }
+}
+
+struct Model {
+    name: &'static str,
+    /// Used for the bulbs lacking the `support` list.
+    methods: &'static [&'static [&'static str]],
+    temperature: Option<TemperatureRange>,
+    /// Of the ambient light of the dual-light fixtures.
+    background_temperature: Option<TemperatureRange>,
+}
+
+const WARM_WHITE: Option<TemperatureRange> = Some(TemperatureRange {
+    min: 2700,
+    max: 6500,
+});
+
+const FULL_WHITE: Option<TemperatureRange> = Some(TemperatureRange {
+    min: 1700,
+    max: 6500,
+});
+
+const NEUTRAL_WHITE: Option<TemperatureRange> = Some(TemperatureRange {
+    min: 2700,
+    max: 6000,
+});
+
+/// Keyed by the model advertised in the discovery replies, or by
+/// the family of the numbered variants, e.g. `ceiling` for
+/// `ceiling24`.
+const MODELS: &[Model] = &[
+    Model {
+        name: "mono",
+        methods: &[BASIC_METHODS],
+        temperature: None,
+        background_temperature: None,
+    },
+    Model {
+        name: "ct_bulb",
+        methods: &[BASIC_METHODS, TEMPERATURE_METHODS],
+        temperature: WARM_WHITE,
+        background_temperature: None,
+    },
+    Model {
+        name: "ceiling",
+        methods: &[BASIC_METHODS, TEMPERATURE_METHODS],
+        temperature: WARM_WHITE,
+        background_temperature: None,
+    },
+    Model {
+        name: "ceilc",
+        methods: &[BASIC_METHODS, TEMPERATURE_METHODS],
+        temperature: NEUTRAL_WHITE,
+        background_temperature: None,
+    },
+    Model {
+        name: "ceiling15",
+        methods: &[BASIC_METHODS, TEMPERATURE_METHODS],
+        temperature: NEUTRAL_WHITE,
+        background_temperature: None,
+    },
+    Model {
+        name: "ceiling4",
+        methods: &[BASIC_METHODS, TEMPERATURE_METHODS, BACKGROUND_METHODS],
+        temperature: WARM_WHITE,
+        background_temperature: FULL_WHITE,
+    },
+    Model {
+        name: "ceiling10",
+        methods: &[BASIC_METHODS, TEMPERATURE_METHODS, BACKGROUND_METHODS],
+        temperature: WARM_WHITE,
+        background_temperature: FULL_WHITE,
+    },
+    Model {
+        name: "ceil",
+        methods: &[BASIC_METHODS, TEMPERATURE_METHODS],
+        temperature: WARM_WHITE,
+        background_temperature: None,
+    },
+    Model {
+        name: "color",
+        methods: &[BASIC_METHODS, COLOR_METHODS],
+        temperature: FULL_WHITE,
+        background_temperature: None,
+    },
+    Model {
+        name: "stripe",
+        methods: &[BASIC_METHODS, COLOR_METHODS],
+        temperature: FULL_WHITE,
+        background_temperature: None,
+    },
+    Model {
+        name: "strip",
+        methods: &[BASIC_METHODS, COLOR_METHODS],
+        temperature: FULL_WHITE,
+        background_temperature: None,
+    },
+    Model {
+        name: "bslamp",
+        methods: &[BASIC_METHODS, COLOR_METHODS],
+        temperature: FULL_WHITE,
+        background_temperature: None,
+    },
+];
+
+/// The model matching `model` exactly, or else its family, i.e. the
+/// longest known name it starts with.
+fn find_model(model: &str) -> Option<&'static Model> {
+    MODELS.iter().find(|known| known.name == model).or_else(|| {
+        MODELS
+            .iter()
+            .filter(|known| model.starts_with(known.name))
+            .max_by_key(|known| known.name.len())
+    })
+}
+
+/// The methods a bulb supports.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Capabilities {
+    methods: BTreeSet<String>,
+    /// `None` if unknown, limited only by [`Temperature`] itself.
+    temperature: Option<TemperatureRange>,
+    background_temperature: Option<TemperatureRange>,
+}
+
+impl Capabilities {
+    /// Use the `support` list from a discovery reply.
+    pub fn from_support<S: AsRef<str>>(support: &[S]) -> Self {
+        Self {
+            methods: support.iter().map(|x| x.as_ref().to_owned()).collect(),
+            temperature: None,
+            background_temperature: None,
+        }
+    }
+
+    /// The capabilities of a known model.
+    pub fn for_model(model: &str) -> Option<Self> {
+        let model = find_model(model)?;
+        Some(Self {
+            methods: model
+                .methods
+                .iter()
+                .flat_map(|methods| methods.iter())
+                .map(|method| method.to_string())
+                .collect(),
+            temperature: model.temperature,
+            background_temperature: model.background_temperature,
+        })
+    }
+
+    /// Use the color temperatures accepted by `model`, if known.
+    pub fn with_temperatures_of(self, model: &str) -> Self {
+        match find_model(model) {
+            Some(model) => Self {
+                temperature: model.temperature,
+                background_temperature: model.background_temperature,
+                ..self
+            },
+            None => self,
+        }
+    }
+
+    /// The color temperatures accepted by the given light.
+    pub fn temperature(&self, channel: Channel) -> Option<TemperatureRange> {
+        match channel {
+            Channel::Main => self.temperature,
+            Channel::Background => self.background_temperature,
+        }
+    }
+
+    /// Validate a color temperature against the range of the given
+    /// light, if known.
+    pub fn check_temperature(
+        &self,
+        channel: Channel,
+        temperature: Temperature,
+    ) -> Result<Temperature, RangeError<u16>> {
+        match self.temperature(channel) {
+            Some(range) => range.check(temperature),
+            None => Ok(temperature),
+        }
+    }
+
+    /// Validate the color temperatures of the flow's steps.
+    pub fn check_flow(&self, channel: Channel, flow: &ColorFlow) -> Result<(), RangeError<u16>> {
+        for step in flow.steps() {
+            if let FlowStep::Temperature { temperature, .. } = step {
+                self.check_temperature(channel, *temperature)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn supports(&self, method: &str) -> bool {
+        self.methods.contains(method)
+    }
+
+    pub fn methods(&self) -> impl Iterator<Item = &str> {
+        self.methods.iter().map(String::as_str)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::params::{BoundedRange, FlowDuration, FlowEnd};
+
+    #[test]
+    fn from_support() {
+        let capabilities = Capabilities::from_support(&["get_prop", "set_power", "set_rgb"]);
+        assert!(capabilities.supports("set_rgb"));
+        assert!(!capabilities.supports("set_ct_abx"));
+    }
+
+    #[test]
+    fn for_model() {
+        let mono = Capabilities::for_model("mono").unwrap();
+        assert!(mono.supports("set_bright"));
+        assert!(!mono.supports("set_rgb"));
+
+        let ceiling = Capabilities::for_model("ceiling4").unwrap();
+        assert!(ceiling.supports("set_ct_abx"));
+        assert!(ceiling.supports("bg_set_rgb"));
+
+        assert!(Capabilities::for_model("toaster").is_none());
+    }
+
+    #[test]
+    fn numbered_models() {
+        let ceiling = Capabilities::for_model("ceiling24").unwrap();
+        assert!(ceiling
+            .check_temperature(Channel::Main, Temperature(1700))
+            .is_err());
+        assert!(!ceiling.supports("bg_set_power"));
+
+        let ceiling = Capabilities::for_model("ceiling10").unwrap();
+        assert!(ceiling.supports("bg_set_power"));
+
+        let ceiling = Capabilities::for_model("ceila").unwrap();
+        assert!(ceiling
+            .check_temperature(Channel::Main, Temperature(6500))
+            .is_ok());
+
+        let ceiling = Capabilities::for_model("ceilc").unwrap();
+        assert_eq!(
+            ceiling
+                .check_temperature(Channel::Main, Temperature(6500))
+                .unwrap_err()
+                .to_string(),
+            "Value 6500 not within [2700..6000]"
+        );
+
+        let bulb = Capabilities::from_support(&["set_ct_abx"]).with_temperatures_of("color8");
+        assert!(bulb
+            .check_temperature(Channel::Main, Temperature(1700))
+            .is_ok());
+
+        let strip = Capabilities::for_model("strip1").unwrap();
+        assert!(strip.supports("set_rgb"));
+    }
+
+    #[test]
+    fn check_temperature() {
+        let ceiling = Capabilities::for_model("ceiling").unwrap();
+        assert!(ceiling
+            .check_temperature(Channel::Main, Temperature(2700))
+            .is_ok());
+        assert!(ceiling
+            .check_temperature(Channel::Main, Temperature(1700))
+            .is_err());
+
+        let unknown = Capabilities::from_support(&["set_ct_abx"]);
+        assert!(unknown
+            .check_temperature(Channel::Main, Temperature(1700))
+            .is_ok());
+    }
+
+    #[test]
+    fn check_flow() {
+        let ceiling = Capabilities::for_model("ceiling1").unwrap();
+        let flow = ColorFlow::new(FlowEnd::Stay).temperature(
+            FlowDuration::new(500).unwrap(),
+            Temperature(1700),
+            None,
+        );
+        assert!(ceiling.check_flow(Channel::Main, &flow).is_err());
+        assert!(ceiling.check_flow(Channel::Background, &flow).is_ok());
+    }
+
+    #[test]
+    fn background_temperature() {
+        let ceiling = Capabilities::from_support(&["set_ct_abx", "bg_set_ct_abx"])
+            .with_temperatures_of("ceiling4");
+        assert!(ceiling
+            .check_temperature(Channel::Main, Temperature(1700))
+            .is_err());
+        assert!(ceiling
+            .check_temperature(Channel::Background, Temperature(1700))
+            .is_ok());
+        assert_eq!(
+            ceiling.temperature(Channel::Background),
+            Some(TemperatureRange {
+                min: 1700,
+                max: 6500
+            })
+        );
+    }
+}
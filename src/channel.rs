@@ -0,0 +1,121 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::borrow::Cow;
+
+use crate::{BulbCommands, BulbError, Capabilities, Property, Response};
+
+/// The commands having a `bg_` counterpart for the background light.
+const BACKGROUND_METHODS: &[&str] = &[
+    "set_power",
+    "set_bright",
+    "set_ct_abx",
+    "set_rgb",
+    "set_hsv",
+    "set_scene",
+    "set_default",
+    "set_adjust",
+    "adjust_bright",
+    "adjust_ct",
+    "adjust_color",
+    "start_cf",
+    "stop_cf",
+    "toggle",
+];
+
+/// Which light of a dual-light fixture (e.g. a ceiling light with
+/// an ambient ring) a command controls.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Channel {
+    #[default]
+    Main,
+    Background,
+}
+
+impl Channel {
+    /// The name of the `method` command for this channel.
+    pub fn method<'a>(&self, method: &'a str) -> Cow<'a, str> {
+        match self {
+            Channel::Background if BACKGROUND_METHODS.contains(&method) => {
+                Cow::Owned(format!("bg_{method}"))
+            }
+            _ => Cow::Borrowed(method),
+        }
+    }
+
+    /// The counterpart of the `prop` property for this channel.
+    pub fn prop(&self, prop: Property) -> Property {
+        match self {
+            Channel::Background => prop.background().unwrap_or(prop),
+            Channel::Main => prop,
+        }
+    }
+}
+
+/// The commands sent to a single [`Channel`], created with
+/// [`BulbCommands::on`].
+///
+/// The properties read with it keep their main light names.
+#[derive(Debug)]
+pub struct OnChannel<'a, C> {
+    commands: &'a mut C,
+    channel: Channel,
+}
+
+impl<'a, C> OnChannel<'a, C> {
+    pub fn new(commands: &'a mut C, channel: Channel) -> Self {
+        Self { commands, channel }
+    }
+}
+
+impl<C: BulbCommands> BulbCommands for OnChannel<'_, C> {
+    fn capabilities(&self) -> Option<&Capabilities> {
+        self.commands.capabilities()
+    }
+
+    fn channel(&self) -> Channel {
+        self.channel
+    }
+
+    async fn request(&mut self, method: &str, params: Vec<Value>) -> Result<Response, BulbError> {
+        let params = match method {
+            "get_prop" => params
+                .into_iter()
+                .map(|param| match param.as_str().map(str::parse) {
+                    Some(Ok(prop)) => self.channel.prop(prop).name().into(),
+                    _ => param,
+                })
+                .collect(),
+            _ => params,
+        };
+        self.commands
+            .request(&self.channel.method(method), params)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn main_names() {
+        assert_eq!(Channel::Main.method("set_power"), "set_power");
+        assert_eq!(Channel::Main.prop(Property::ColorMode), Property::ColorMode);
+    }
+
+    #[test]
+    fn background_names() {
+        assert_eq!(Channel::Background.method("set_power"), "bg_set_power");
+        assert_eq!(Channel::Background.method("get_prop"), "get_prop");
+        assert_eq!(
+            Channel::Background.prop(Property::Bright),
+            Property::BgBright
+        );
+        assert_eq!(
+            Channel::Background.prop(Property::ColorMode),
+            Property::BgLmode
+        );
+        assert_eq!(Channel::Background.prop(Property::Name), Property::Name);
+    }
+}
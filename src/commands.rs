@@ -0,0 +1,368 @@
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+use crate::params::{
+    AdjustAction, AdjustProp, Brightness, Color, ColorFlow, Effect, Hsv, Hue, Percentage,
+    Saturation, Scene, Temperature,
+};
+use crate::{
+    BulbError, BulbState, Capabilities, Channel, OnChannel, Property, PropertyValue, Response,
+};
+
+/// The timer turning the bulb off, as set with `cron_add`.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct SleepTimer {
+    /// The minutes left until the bulb turns off.
+    pub minutes: u16,
+}
+
+/// The `cron_*` type of the power-off timer, the only one supported.
+const CRON_POWER_OFF: u8 = 0;
+
+/// The commands understood by the bulbs.
+///
+/// Implemented by everything able to deliver a single command to
+/// a bulb, so that the wrappers around [`crate::BulbConnection`]
+/// expose the same API.
+#[allow(async_fn_in_trait)]
+pub trait BulbCommands {
+    /// Send a raw command and wait for its response.
+    async fn request(&mut self, method: &str, params: Vec<Value>) -> Result<Response, BulbError>;
+
+    /// What the bulb supports, `None` if unknown.
+    fn capabilities(&self) -> Option<&Capabilities> {
+        None
+    }
+
+    /// The light of a dual-light fixture the commands control.
+    fn channel(&self) -> Channel {
+        Channel::Main
+    }
+
+    /// Send the subsequent commands to the given light of
+    /// a dual-light fixture.
+    fn on(&mut self, channel: Channel) -> OnChannel<'_, Self>
+    where
+        Self: Sized,
+    {
+        OnChannel::new(self, channel)
+    }
+
+    async fn set_power(&mut self, state: bool, effect: Effect) -> Result<Response, BulbError> {
+        let state = match state {
+            true => "on",
+            false => "off",
+        };
+
+        self.request(
+            "set_power",
+            vec![
+                state.into(),
+                effect.effect().into(),
+                effect.duration().into(),
+            ],
+        )
+        .await
+    }
+
+    /// Switch the power state without reading it first.
+    async fn toggle(&mut self) -> Result<Response, BulbError> {
+        self.request("toggle", vec![]).await
+    }
+
+    /// Toggle both the main and the background light of a dual-light
+    /// fixture.
+    async fn toggle_device(&mut self) -> Result<Response, BulbError> {
+        self.request("dev_toggle", vec![]).await
+    }
+
+    async fn set_brightness(
+        &mut self,
+        Brightness(brightness): Brightness,
+        effect: Effect,
+    ) -> Result<Response, BulbError> {
+        self.request(
+            "set_bright",
+            vec![
+                brightness.into(),
+                effect.effect().into(),
+                effect.duration().into(),
+            ],
+        )
+        .await
+    }
+
+    async fn adjust_brightness(
+        &mut self,
+        Percentage(percentage): Percentage,
+        duration: u16,
+    ) -> Result<Response, BulbError> {
+        self.request("adjust_bright", vec![percentage.into(), duration.into()])
+            .await
+    }
+
+    async fn adjust_temperature(
+        &mut self,
+        Percentage(percentage): Percentage,
+        duration: u16,
+    ) -> Result<Response, BulbError> {
+        self.request("adjust_ct", vec![percentage.into(), duration.into()])
+            .await
+    }
+
+    async fn adjust_color(
+        &mut self,
+        Percentage(percentage): Percentage,
+        duration: u16,
+    ) -> Result<Response, BulbError> {
+        self.request("adjust_color", vec![percentage.into(), duration.into()])
+            .await
+    }
+
+    /// Change a value by a step chosen by the bulb.
+    async fn set_adjust(
+        &mut self,
+        action: AdjustAction,
+        prop: AdjustProp,
+    ) -> Result<Response, BulbError> {
+        self.request(
+            "set_adjust",
+            vec![action.action().into(), prop.prop().into()],
+        )
+        .await
+    }
+
+    /// Fails without sending anything if the temperature is outside of
+    /// the range known for the bulb.
+    async fn set_temperature(
+        &mut self,
+        temperature: Temperature,
+        effect: Effect,
+    ) -> Result<Response, BulbError> {
+        let Temperature(temperature) = match self.capabilities() {
+            Some(capabilities) => capabilities.check_temperature(self.channel(), temperature)?,
+            None => temperature,
+        };
+        self.request(
+            "set_ct_abx",
+            vec![
+                temperature.into(),
+                effect.effect().into(),
+                effect.duration().into(),
+            ],
+        )
+        .await
+    }
+
+    async fn set_color(
+        &mut self,
+        Color(color): Color,
+        effect: Effect,
+    ) -> Result<Response, BulbError> {
+        self.request(
+            "set_rgb",
+            vec![
+                color.into(),
+                effect.effect().into(),
+                effect.duration().into(),
+            ],
+        )
+        .await
+    }
+
+    async fn set_hsv(
+        &mut self,
+        Hsv {
+            hue: Hue(hue),
+            saturation: Saturation(saturation),
+        }: Hsv,
+        effect: Effect,
+    ) -> Result<Response, BulbError> {
+        self.request(
+            "set_hsv",
+            vec![
+                hue.into(),
+                saturation.into(),
+                effect.effect().into(),
+                effect.duration().into(),
+            ],
+        )
+        .await
+    }
+
+    async fn set_scene(&mut self, scene: &Scene) -> Result<Response, BulbError> {
+        let mut params = vec![scene.class().into()];
+        match scene {
+            Scene::Color {
+                color: Color(color),
+                brightness: Brightness(brightness),
+            } => params.extend([(*color).into(), (*brightness).into()]),
+            Scene::Hsv {
+                hsv:
+                    Hsv {
+                        hue: Hue(hue),
+                        saturation: Saturation(saturation),
+                    },
+                brightness: Brightness(brightness),
+            } => params.extend([(*hue).into(), (*saturation).into(), (*brightness).into()]),
+            Scene::Temperature {
+                temperature,
+                brightness: Brightness(brightness),
+            } => {
+                let Temperature(temperature) = match self.capabilities() {
+                    Some(capabilities) => {
+                        capabilities.check_temperature(self.channel(), *temperature)?
+                    }
+                    None => *temperature,
+                };
+                params.extend([temperature.into(), (*brightness).into()])
+            }
+            Scene::ColorFlow(flow) => {
+                flow.check()?;
+                if let Some(capabilities) = self.capabilities() {
+                    capabilities.check_flow(self.channel(), flow)?;
+                }
+                params.extend([
+                    flow.count().into(),
+                    flow.end().action().into(),
+                    flow.expression().into(),
+                ])
+            }
+            Scene::AutoDelayOff {
+                brightness: Brightness(brightness),
+                minutes,
+            } => params.extend([(*brightness).into(), (*minutes).into()]),
+        }
+        self.request("set_scene", params).await
+    }
+
+    /// Save the current state as the one restored after the bulb
+    /// gets power cycled, e.g. with a wall switch.
+    async fn set_default(&mut self) -> Result<Response, BulbError> {
+        self.request("set_default", vec![]).await
+    }
+
+    /// Store the name on the bulb itself.
+    async fn set_name(&mut self, name: &str) -> Result<Response, BulbError> {
+        self.request("set_name", vec![name.into()]).await
+    }
+
+    /// The name stored on the bulb, if it has been given one.
+    async fn get_name(&mut self) -> Result<Option<String>, BulbError> {
+        let props = self.get_props(&[Property::Name]).await?;
+        Ok(props.into_iter().next().filter(|name| !name.is_empty()))
+    }
+
+    async fn start_color_flow(&mut self, flow: &ColorFlow) -> Result<Response, BulbError> {
+        flow.check()?;
+        if let Some(capabilities) = self.capabilities() {
+            capabilities.check_flow(self.channel(), flow)?;
+        }
+        self.request(
+            "start_cf",
+            vec![
+                flow.count().into(),
+                flow.end().action().into(),
+                flow.expression().into(),
+            ],
+        )
+        .await
+    }
+
+    async fn stop_color_flow(&mut self) -> Result<Response, BulbError> {
+        self.request("stop_cf", vec![]).await
+    }
+
+    /// Turn the bulb off after `minutes`, replacing the previous timer.
+    ///
+    /// The timer runs on the bulb itself.
+    async fn add_sleep_timer(&mut self, minutes: u16) -> Result<Response, BulbError> {
+        self.request("cron_add", vec![CRON_POWER_OFF.into(), minutes.into()])
+            .await
+    }
+
+    async fn get_sleep_timer(&mut self) -> Result<Option<SleepTimer>, BulbError> {
+        let response = self
+            .request("cron_get", vec![CRON_POWER_OFF.into()])
+            .await?;
+        let values = response
+            .result
+            .ok_or_else(|| BulbError::UnexpectedResult("No results in the response".to_owned()))?;
+        let Some(timer) = values.first() else {
+            return Ok(None);
+        };
+        let minutes = timer
+            .get("delay")
+            .and_then(Value::as_u64)
+            .and_then(|delay| u16::try_from(delay).ok())
+            .ok_or_else(|| BulbError::UnexpectedResult(format!("Invalid timer: {timer}")))?;
+        Ok(Some(SleepTimer { minutes }))
+    }
+
+    async fn delete_sleep_timer(&mut self) -> Result<Response, BulbError> {
+        self.request("cron_del", vec![CRON_POWER_OFF.into()]).await
+    }
+
+    /// Read the raw values of the properties.
+    async fn get_props(&mut self, props: &[Property]) -> Result<Vec<String>, BulbError> {
+        let expected = props.len();
+        let props = props.iter().map(|prop| Value::from(prop.name())).collect();
+        let response = self.request("get_prop", props).await?;
+        let values = response
+            .result
+            .ok_or_else(|| BulbError::UnexpectedResult("No results in the response".to_owned()))?;
+        if values.len() != expected {
+            return Err(BulbError::UnexpectedResult(format!(
+                "Expected {} props, got {}",
+                expected,
+                values.len()
+            )));
+        }
+        values
+            .into_iter()
+            .map(|value| match value {
+                Value::String(value) => Ok(value),
+                value => Err(BulbError::UnexpectedResult(format!(
+                    "Invalid prop value: {value}"
+                ))),
+            })
+            .collect()
+    }
+
+    /// `None` if the bulb doesn't support the HSV colors.
+    async fn get_hsv(&mut self) -> Result<Option<Hsv>, BulbError> {
+        let values = self.get_values(&[Property::Hue, Property::Sat]).await?;
+        match values.as_slice() {
+            &[PropertyValue::Hue(hue), PropertyValue::Saturation(saturation)] => {
+                Ok(Some(Hsv { hue, saturation }))
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Read both lights of the bulb, so not meant for [`OnChannel`];
+    /// see [`BulbState::for_channel`] instead.
+    async fn get_state(&mut self) -> Result<BulbState, BulbError> {
+        let props = self.get_props_map(BulbState::PROPS).await?;
+        BulbState::from_props(&props)
+    }
+
+    async fn get_props_map(
+        &mut self,
+        props: &[Property],
+    ) -> Result<BTreeMap<Property, String>, BulbError> {
+        let values = self.get_props(props).await?;
+        Ok(BTreeMap::from_iter(props.iter().copied().zip(values)))
+    }
+
+    /// Read and decode the values of the properties.
+    async fn get_values(&mut self, props: &[Property]) -> Result<Vec<PropertyValue>, BulbError> {
+        let values = self.get_props(props).await?;
+        props
+            .iter()
+            .zip(values)
+            .map(|(prop, value)| prop.decode(&value))
+            .collect()
+    }
+}
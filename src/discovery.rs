@@ -0,0 +1,256 @@
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use thiserror::Error;
+use tokio::net::UdpSocket;
+use tokio::time::{self, Duration, Instant};
+
+use crate::{Bulb, BulbInfo};
+
+pub const MULTICAST_ADDR: SocketAddr =
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(239, 255, 255, 250), 1982));
+
+const SEARCH_REQUEST: &str = "M-SEARCH * HTTP/1.1\r\n\
+                              HOST: 239.255.255.250:1982\r\n\
+                              MAN: \"ssdp:discover\"\r\n\
+                              ST: wifi_bulb\r\n";
+
+const REPLY_STATUS: &str = "HTTP/1.1 200 OK";
+const NOTIFY_STATUS: &str = "NOTIFY * HTTP/1.1";
+
+/// How long the bulb's details are valid if not stated otherwise.
+pub const DEFAULT_MAX_AGE: Duration = Duration::from_secs(3600);
+
+/// The headers describing the message itself rather than the bulb state.
+const NON_PROP_HEADERS: &[&str] = &[
+    "cache-control",
+    "host",
+    "nts",
+    "date",
+    "ext",
+    "location",
+    "server",
+    "id",
+    "model",
+    "fw_ver",
+    "support",
+];
+
+#[derive(Error, Debug)]
+pub enum DiscoveryError {
+    #[error("Unexpected status line: {}", .0)]
+    InvalidStatus(String),
+    #[error("Missing header: {}", .0)]
+    MissingHeader(&'static str),
+    #[error("Invalid bulb location: {}", .0)]
+    InvalidLocation(String),
+}
+
+/// A bulb announcing itself in the network.
+#[derive(Clone, Debug)]
+pub struct Advertisement {
+    pub bulb: Bulb,
+    /// For how long the advertised details stay valid.
+    pub max_age: Duration,
+}
+
+/// Parse a reply to the `M-SEARCH` probe.
+pub fn parse_reply(reply: &str) -> Result<Bulb, DiscoveryError> {
+    parse_message(reply, REPLY_STATUS).map(|advertisement| advertisement.bulb)
+}
+
+/// Parse a `NOTIFY` packet periodically multicast by the bulbs.
+pub fn parse_notify(notify: &str) -> Result<Advertisement, DiscoveryError> {
+    parse_message(notify, NOTIFY_STATUS)
+}
+
+fn parse_message(message: &str, expected_status: &str) -> Result<Advertisement, DiscoveryError> {
+    let mut lines = message.lines().map(str::trim_end);
+
+    let status = lines.next().unwrap_or_default();
+    if status != expected_status {
+        return Err(DiscoveryError::InvalidStatus(status.to_owned()));
+    }
+
+    let mut headers: BTreeMap<String, String> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(key, value)| (key.trim().to_lowercase(), value.trim().to_owned()))
+        .collect();
+    let mut take = |header: &'static str| {
+        headers
+            .remove(header)
+            .ok_or(DiscoveryError::MissingHeader(header))
+    };
+
+    let location = take("location")?;
+    let addr = location
+        .strip_prefix("yeelight://")
+        .and_then(|addr| addr.parse().ok())
+        .ok_or_else(|| DiscoveryError::InvalidLocation(location.clone()))?;
+    let id = take("id")?;
+    let model = take("model")?;
+    let fw_ver = take("fw_ver")?;
+    let support = take("support")?
+        .split_whitespace()
+        .map(str::to_owned)
+        .collect();
+    let max_age = take("cache-control")
+        .ok()
+        .and_then(|value| value.strip_prefix("max-age=")?.parse().ok())
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_MAX_AGE);
+
+    headers.retain(|key, _| !NON_PROP_HEADERS.contains(&key.as_str()));
+
+    let bulb = Bulb::with_info(
+        addr,
+        BulbInfo {
+            id,
+            model,
+            fw_ver,
+            support,
+            props: headers,
+        },
+    );
+    Ok(Advertisement { bulb, max_age })
+}
+
+/// Discover the bulbs in the local network.
+///
+/// Collects the replies for the whole `timeout` duration.
+pub async fn discover(timeout: Duration) -> io::Result<Vec<Bulb>> {
+    discover_at(MULTICAST_ADDR, timeout).await
+}
+
+/// Send the `M-SEARCH` probe to `target` instead of the multicast group.
+pub async fn discover_at(target: SocketAddr, timeout: Duration) -> io::Result<Vec<Bulb>> {
+    let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, 0)).await?;
+    info!("Searching for bulbs at: {}", target);
+    socket.send_to(SEARCH_REQUEST.as_bytes(), target).await?;
+
+    let deadline = Instant::now() + timeout;
+    let mut bulbs: Vec<Bulb> = vec![];
+    let mut buf = [0; 2048];
+    while let Ok(received) = time::timeout_at(deadline, socket.recv_from(&mut buf)).await {
+        let (len, from) = received?;
+        let reply = String::from_utf8_lossy(&buf[..len]);
+        match parse_reply(&reply) {
+            Ok(bulb) => {
+                let id = bulb.info().map(|info| &info.id);
+                if bulbs
+                    .iter()
+                    .any(|known| known.info().map(|info| &info.id) == id)
+                {
+                    continue;
+                }
+                info!("Discovered: {}", bulb.addr());
+                bulbs.push(bulb);
+            }
+            Err(err) => {
+                warn!("Invalid reply from {}, ignoring: {}", from, err);
+            }
+        }
+    }
+
+    Ok(bulbs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REPLY: &str = "HTTP/1.1 200 OK\r\n\
+                         Cache-Control: max-age=3600\r\n\
+                         Date: \r\n\
+                         Ext: \r\n\
+                         Location: yeelight://192.168.1.239:55443\r\n\
+                         Server: POSIX UPnP/1.0 YGLC/1\r\n\
+                         id: 0x000000000015243f\r\n\
+                         model: color\r\n\
+                         fw_ver: 18\r\n\
+                         support: get_prop set_default set_power toggle set_bright\r\n\
+                         power: on\r\n\
+                         bright: 100\r\n\
+                         color_mode: 2\r\n\
+                         ct: 4000\r\n\
+                         rgb: 16711680\r\n\
+                         hue: 100\r\n\
+                         sat: 35\r\n\
+                         name: my_bulb\r\n";
+
+    #[test]
+    fn parse_valid_reply() {
+        let bulb = parse_reply(REPLY).unwrap();
+        assert_eq!(bulb.addr(), "192.168.1.239:55443".parse().unwrap());
+
+        let info = bulb.info().unwrap();
+        assert_eq!(info.id, "0x000000000015243f");
+        assert_eq!(info.model, "color");
+        assert_eq!(info.fw_ver, "18");
+        assert_eq!(
+            info.support,
+            [
+                "get_prop",
+                "set_default",
+                "set_power",
+                "toggle",
+                "set_bright"
+            ]
+        );
+        assert_eq!(info.props["power"], "on");
+        assert_eq!(info.props["rgb"], "16711680");
+        assert_eq!(info.props["name"], "my_bulb");
+        assert!(!info.props.contains_key("location"));
+        assert!(!info.props.contains_key("cache-control"));
+
+        let capabilities = bulb.capabilities().unwrap();
+        assert!(capabilities.supports("set_bright"));
+        assert!(!capabilities.supports("set_rgb"));
+    }
+
+    #[test]
+    fn parse_valid_notify() {
+        let notify = REPLY
+            .replace(REPLY_STATUS, NOTIFY_STATUS)
+            .replace("max-age=3600", "max-age=60");
+        let advertisement = parse_notify(&notify).unwrap();
+        assert_eq!(advertisement.max_age, Duration::from_secs(60));
+        assert_eq!(advertisement.bulb.info().unwrap().id, "0x000000000015243f");
+
+        let notify = notify.replace("Cache-Control: max-age=60\r\n", "");
+        let advertisement = parse_notify(&notify).unwrap();
+        assert_eq!(advertisement.max_age, DEFAULT_MAX_AGE);
+
+        // A notification is not a valid reply and vice versa.
+        assert!(parse_reply(&notify).is_err());
+        assert!(parse_notify(REPLY).is_err());
+    }
+
+    #[test]
+    fn parse_invalid_status() {
+        let result = parse_reply(&REPLY.replace("200 OK", "404 Not Found"));
+        assert!(matches!(result, Err(DiscoveryError::InvalidStatus(_))));
+
+        let result = parse_reply("");
+        assert!(matches!(result, Err(DiscoveryError::InvalidStatus(_))));
+    }
+
+    #[test]
+    fn parse_missing_header() {
+        let result = parse_reply(&REPLY.replace("model: color\r\n", ""));
+        assert!(matches!(
+            result,
+            Err(DiscoveryError::MissingHeader("model"))
+        ));
+    }
+
+    #[test]
+    fn parse_invalid_location() {
+        let result = parse_reply(&REPLY.replace("yeelight://", "http://"));
+        assert!(matches!(result, Err(DiscoveryError::InvalidLocation(_))));
+
+        let result = parse_reply(&REPLY.replace(":55443", ""));
+        assert!(matches!(result, Err(DiscoveryError::InvalidLocation(_))));
+    }
+}
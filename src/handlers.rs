@@ -1,151 +1,394 @@
-use std::{collections::BTreeMap, str::FromStr};
+use std::str::FromStr;
 
-use axum::{extract::Query, http::StatusCode, response::Json};
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json,
+};
 use serde::Deserialize;
 use serde_json::{json, Value};
 
 use yeetlight::*;
 
+fn bulb_error(error: BulbError) -> (StatusCode, String) {
+    let status = match error {
+        BulbError::Connect(_) => StatusCode::SERVICE_UNAVAILABLE,
+        BulbError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
+        BulbError::Bulb { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+        BulbError::Superseded => StatusCode::CONFLICT,
+        BulbError::Unsupported(_) => StatusCode::NOT_IMPLEMENTED,
+        BulbError::OutOfRange(_) | BulbError::InvalidFlow(_) => StatusCode::UNPROCESSABLE_ENTITY,
+        BulbError::Disconnected
+        | BulbError::Io(_)
+        | BulbError::Protocol(_)
+        | BulbError::UnexpectedResult(_) => StatusCode::BAD_GATEWAY,
+    };
+    (status, error.to_string())
+}
+
+/// The bulb by its id, following its address changes, or directly by
+/// its address.
+fn resolve_bulb(pool: &ConnectionPool, bulb: &str) -> Result<Bulb, (StatusCode, String)> {
+    match pool.find(bulb) {
+        Some(bulb) => Ok(bulb),
+        None => Bulb::from_str(bulb).map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string())),
+    }
+}
+
 #[derive(Debug, Deserialize)]
 pub struct PowerParams {
     bulb: String,
+    #[serde(default)]
+    channel: Channel,
 }
 
 pub async fn power_on(
+    State(pool): State<ConnectionPool>,
     Query(params): Query<PowerParams>,
 ) -> Result<Json<Response>, (StatusCode, String)> {
-    let bulb = Bulb::from_str(&params.bulb)
-        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
-    let response = bulb
-        .connect()
+    let bulb = resolve_bulb(&pool, &params.bulb)?;
+    let response = pool
+        .get(&bulb)
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(bulb_error)?
+        .on(params.channel)
         .set_power(true, Effect::Smooth(500))
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .map_err(bulb_error)?;
     Ok(Json(response))
 }
 pub async fn power_off(
+    State(pool): State<ConnectionPool>,
     Query(params): Query<PowerParams>,
 ) -> Result<Json<Response>, (StatusCode, String)> {
-    let bulb = Bulb::from_str(&params.bulb)
-        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
-    let response = bulb
-        .connect()
+    let bulb = resolve_bulb(&pool, &params.bulb)?;
+    let response = pool
+        .get(&bulb)
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(bulb_error)?
+        .on(params.channel)
         .set_power(false, Effect::Smooth(500))
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .map_err(bulb_error)?;
     Ok(Json(response))
 }
+
+#[derive(Debug, Deserialize)]
+pub struct ToggleParams {
+    bulb: String,
+    #[serde(default)]
+    channel: Channel,
+    /// Toggle both lights of a dual-light fixture, ignoring `channel`.
+    #[serde(default)]
+    device: bool,
+}
 pub async fn power_toggle(
-    Query(params): Query<PowerParams>,
-) -> Result<Json<Response>, (StatusCode, String)> {
-    let bulb = Bulb::from_str(&params.bulb)
-        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
-    let props_response = bulb
-        .connect()
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
-        .get_props(&["power"])
-        .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    let power_state = props_response
-        .first()
-        .expect("Got a response but with no expected value");
-    match power_state.as_str() {
-        "on" => power_off(Query(params)).await,
-        "off" => power_on(Query(params)).await,
-        _ => Err((
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Unexpected light state: {power_state}"),
-        )),
+    State(pool): State<ConnectionPool>,
+    Query(params): Query<ToggleParams>,
+) -> Result<Json<Value>, (StatusCode, String)> {
+    let bulb = resolve_bulb(&pool, &params.bulb)?;
+    let mut connection = pool.get(&bulb).await.map_err(bulb_error)?;
+    let mut connection = connection.on(params.channel);
+    if params.device {
+        connection.toggle_device().await.map_err(bulb_error)?;
+    } else {
+        connection.toggle().await.map_err(bulb_error)?;
     }
+    let values = connection
+        .get_values(&[Property::Power])
+        .await
+        .map_err(bulb_error)?;
+    Ok(Json(json!({ "power": values[0] })))
 }
 
 #[derive(Debug, Deserialize)]
 pub struct BrightnessParams {
     bulb: String,
+    #[serde(default)]
+    channel: Channel,
     brightness: u16,
 }
 pub async fn brightness(
+    State(pool): State<ConnectionPool>,
     Query(params): Query<BrightnessParams>,
 ) -> Result<Json<Response>, (StatusCode, String)> {
-    let bulb = Bulb::from_str(&params.bulb)
-        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
+    let bulb = resolve_bulb(&pool, &params.bulb)?;
     let brightness = Brightness::new(params.brightness)
         .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
-    let response = bulb
-        .connect()
+    let response = pool
+        .get(&bulb)
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(bulb_error)?
+        .on(params.channel)
         .set_brightness(brightness, Effect::Smooth(500))
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .map_err(bulb_error)?;
+    Ok(Json(response))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AdjustParams {
+    bulb: String,
+    #[serde(default)]
+    channel: Channel,
+    prop: AdjustProp,
+    percentage: i16,
+    duration: Option<u16>,
+}
+pub async fn adjust(
+    State(pool): State<ConnectionPool>,
+    Query(params): Query<AdjustParams>,
+) -> Result<Json<Response>, (StatusCode, String)> {
+    let bulb = resolve_bulb(&pool, &params.bulb)?;
+    let percentage = Percentage::new(params.percentage)
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
+    let duration = params.duration.unwrap_or(500);
+    let mut connection = pool.get(&bulb).await.map_err(bulb_error)?;
+    let mut connection = connection.on(params.channel);
+    let response = match params.prop {
+        AdjustProp::Brightness => connection.adjust_brightness(percentage, duration).await,
+        AdjustProp::Temperature => connection.adjust_temperature(percentage, duration).await,
+        AdjustProp::Color => connection.adjust_color(percentage, duration).await,
+    }
+    .map_err(bulb_error)?;
+    Ok(Json(response))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StepParams {
+    bulb: String,
+    #[serde(default)]
+    channel: Channel,
+    prop: AdjustProp,
+    action: AdjustAction,
+}
+pub async fn step(
+    State(pool): State<ConnectionPool>,
+    Query(params): Query<StepParams>,
+) -> Result<Json<Response>, (StatusCode, String)> {
+    let bulb = resolve_bulb(&pool, &params.bulb)?;
+    let response = pool
+        .get(&bulb)
+        .await
+        .map_err(bulb_error)?
+        .on(params.channel)
+        .set_adjust(params.action, params.prop)
+        .await
+        .map_err(bulb_error)?;
     Ok(Json(response))
 }
 
 #[derive(Debug, Deserialize)]
 pub struct TemperatureParams {
     bulb: String,
+    #[serde(default)]
+    channel: Channel,
     temperature: u16,
 }
 pub async fn temperature(
+    State(pool): State<ConnectionPool>,
     Query(params): Query<TemperatureParams>,
 ) -> Result<Json<Response>, (StatusCode, String)> {
-    let bulb = Bulb::from_str(&params.bulb)
-        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
+    let bulb = resolve_bulb(&pool, &params.bulb)?;
     let temperature = Temperature::new(params.temperature)
         .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
-    let response = bulb
-        .connect()
+    let response = pool
+        .get(&bulb)
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(bulb_error)?
+        .on(params.channel)
         .set_temperature(temperature, Effect::Smooth(500))
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .map_err(bulb_error)?;
     Ok(Json(response))
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ColorParams {
     bulb: String,
+    #[serde(default)]
+    channel: Channel,
     color: String,
 }
 pub async fn color(
+    State(pool): State<ConnectionPool>,
     Query(params): Query<ColorParams>,
 ) -> Result<Json<Response>, (StatusCode, String)> {
-    let bulb = Bulb::from_str(&params.bulb)
-        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
-    let color = Color::from_hex(&params.color)
+    let bulb = resolve_bulb(&pool, &params.bulb)?;
+    let color = Color::from_str(&params.color)
         .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
-    let response = bulb
-        .connect()
+    let response = pool
+        .get(&bulb)
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
+        .map_err(bulb_error)?
+        .on(params.channel)
         .set_color(color, Effect::Smooth(500))
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+        .map_err(bulb_error)?;
     Ok(Json(response))
 }
 
+#[derive(Debug, Deserialize)]
+pub struct HsvParams {
+    bulb: String,
+    #[serde(default)]
+    channel: Channel,
+    hue: u16,
+    saturation: u16,
+}
+pub async fn hsv(
+    State(pool): State<ConnectionPool>,
+    Query(params): Query<HsvParams>,
+) -> Result<Json<Response>, (StatusCode, String)> {
+    let bulb = resolve_bulb(&pool, &params.bulb)?;
+    let hsv = Hsv::new(params.hue, params.saturation)
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
+    let response = pool
+        .get(&bulb)
+        .await
+        .map_err(bulb_error)?
+        .on(params.channel)
+        .set_hsv(hsv, Effect::Smooth(500))
+        .await
+        .map_err(bulb_error)?;
+    Ok(Json(response))
+}
+
+pub async fn save_default(
+    State(pool): State<ConnectionPool>,
+    Query(params): Query<PowerParams>,
+) -> Result<Json<Response>, (StatusCode, String)> {
+    let bulb = resolve_bulb(&pool, &params.bulb)?;
+    let response = pool
+        .get(&bulb)
+        .await
+        .map_err(bulb_error)?
+        .on(params.channel)
+        .set_default()
+        .await
+        .map_err(bulb_error)?;
+    Ok(Json(response))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NameParams {
+    bulb: String,
+    name: String,
+}
+pub async fn set_name(
+    State(pool): State<ConnectionPool>,
+    Query(params): Query<NameParams>,
+) -> Result<Json<Response>, (StatusCode, String)> {
+    let bulb = resolve_bulb(&pool, &params.bulb)?;
+    let response = pool
+        .get(&bulb)
+        .await
+        .map_err(bulb_error)?
+        .set_name(&params.name)
+        .await
+        .map_err(bulb_error)?;
+    Ok(Json(response))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TimerParams {
+    bulb: String,
+    minutes: u16,
+}
+pub async fn add_timer(
+    State(pool): State<ConnectionPool>,
+    Query(params): Query<TimerParams>,
+) -> Result<Json<Response>, (StatusCode, String)> {
+    let bulb = resolve_bulb(&pool, &params.bulb)?;
+    let response = pool
+        .get(&bulb)
+        .await
+        .map_err(bulb_error)?
+        .add_sleep_timer(params.minutes)
+        .await
+        .map_err(bulb_error)?;
+    Ok(Json(response))
+}
+
+pub async fn get_timer(
+    State(pool): State<ConnectionPool>,
+    Query(params): Query<PowerParams>,
+) -> Result<Json<Option<SleepTimer>>, (StatusCode, String)> {
+    let bulb = resolve_bulb(&pool, &params.bulb)?;
+    let timer = pool
+        .get(&bulb)
+        .await
+        .map_err(bulb_error)?
+        .get_sleep_timer()
+        .await
+        .map_err(bulb_error)?;
+    Ok(Json(timer))
+}
+
+pub async fn delete_timer(
+    State(pool): State<ConnectionPool>,
+    Query(params): Query<PowerParams>,
+) -> Result<Json<Response>, (StatusCode, String)> {
+    let bulb = resolve_bulb(&pool, &params.bulb)?;
+    let response = pool
+        .get(&bulb)
+        .await
+        .map_err(bulb_error)?
+        .delete_sleep_timer()
+        .await
+        .map_err(bulb_error)?;
+    Ok(Json(response))
+}
+
+/// The controls the bulb supports, `null` if unknown.
+pub async fn get_capabilities(
+    State(pool): State<ConnectionPool>,
+    Query(params): Query<InfoParams>,
+) -> Result<Json<Option<Value>>, (StatusCode, String)> {
+    let bulb = resolve_bulb(&pool, &params.bulb)?;
+    let bulb = pool.bulb(bulb.addr());
+    let capabilities = bulb.as_ref().and_then(Bulb::capabilities);
+    let capabilities = capabilities.map(|capabilities| {
+        let supports = |method| capabilities.supports(&params.channel.method(method));
+        json!({
+            "power": supports("set_power"),
+            "brightness": supports("set_bright"),
+            "temperature": supports("set_ct_abx"),
+            "rgb": supports("set_rgb"),
+            "hsv": supports("set_hsv"),
+            "timer": supports("cron_add"),
+            "background": supports("bg_set_power"),
+            "temperature_range": capabilities.temperature(params.channel),
+            "methods": capabilities,
+        })
+    });
+    Ok(Json(capabilities))
+}
+
 #[derive(Debug, Deserialize)]
 pub struct InfoParams {
     bulb: String,
+    #[serde(default)]
+    channel: Channel,
 }
 
 pub async fn get_info(
+    State(pool): State<ConnectionPool>,
     Query(params): Query<InfoParams>,
-) -> Result<Json<Value>, (StatusCode, String)> {
-    let bulb = Bulb::from_str(&params.bulb)
-        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e.to_string()))?;
-    let response: BTreeMap<&str, String> = bulb
-        .connect()
+) -> Result<Json<BulbState>, (StatusCode, String)> {
+    let bulb = resolve_bulb(&pool, &params.bulb)?;
+    let state = pool
+        .get(&bulb)
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?
-        .get_props_map(&["power", "bright", "ct", "rgb", "color_mode"])
+        .map_err(bulb_error)?
+        .get_state()
         .await
-        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
-    Ok(Json(json!(response)))
+        .map_err(bulb_error)?;
+    let state = state.for_channel(params.channel).ok_or_else(|| {
+        (
+            StatusCode::NOT_IMPLEMENTED,
+            "No background light on the bulb".to_owned(),
+        )
+    })?;
+    Ok(Json(state))
 }
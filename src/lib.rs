@@ -1,7 +1,29 @@
 pub mod bulb;
 pub mod bulb_connection;
+pub mod capabilities;
+pub mod channel;
+pub mod commands;
+pub mod discovery;
+pub mod music;
 pub mod params;
+pub mod pool;
+pub mod property;
+pub mod rate_limit;
+pub mod reconnect;
+pub mod registry;
+pub mod state;
 
 pub use bulb::*;
 pub use bulb_connection::*;
+pub use capabilities::*;
+pub use channel::*;
+pub use commands::*;
+pub use discovery::*;
+pub use music::*;
 pub use params::*;
+pub use pool::*;
+pub use property::*;
+pub use rate_limit::*;
+pub use reconnect::*;
+pub use registry::*;
+pub use state::*;
@@ -8,10 +8,13 @@ use axum::{
 };
 use axum_embed::ServeEmbed;
 use clap::Parser;
-use log::info;
+use log::{info, warn};
 use rust_embed::RustEmbed;
 use serde_json::Value;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::time::Duration;
 use tower_http::trace::{DefaultMakeSpan, DefaultOnResponse, TraceLayer};
+use yeetlight::{discover, Bulb, Capabilities, ConnectionPool, RegistryEvent, RegistryListener};
 
 mod handlers;
 
@@ -29,7 +32,81 @@ fn config_routes(config: &Option<Value>) -> Router {
     }
 }
 
-fn bulb_v1_routes() -> Router {
+/// Use the models from the config for the bulbs not advertising
+/// their capabilities, and the ids for the bulbs changing addresses.
+fn add_configured_bulbs(pool: &ConnectionPool, config: &Value) {
+    let Some(bulbs) = config["bulbs"].as_object() else {
+        return;
+    };
+    for (name, bulb_config) in bulbs {
+        let addr = bulb_config["addr"].as_str().unwrap_or(name);
+        let mut bulb = match addr.parse::<Bulb>() {
+            Ok(bulb) => bulb,
+            Err(err) => {
+                warn!("Invalid address of {}: {}", name, err);
+                continue;
+            }
+        };
+        if let Some(model) = bulb_config["model"].as_str() {
+            match Capabilities::for_model(model) {
+                Some(capabilities) => bulb = bulb.with_capabilities(capabilities),
+                None => warn!("Unknown model of {}: {}", name, model),
+            }
+        }
+        match bulb_config["id"].as_str() {
+            Some(id) => pool.add_as(id.to_owned(), bulb),
+            None => pool.add(bulb),
+        }
+    }
+}
+
+/// Find the bulbs on the network, learning their capabilities.
+async fn add_discovered_bulbs(pool: ConnectionPool) {
+    match discover(Duration::from_secs(2)).await {
+        Ok(bulbs) => {
+            for bulb in bulbs {
+                info!("Discovered: {}", bulb.addr());
+                pool.add(bulb);
+            }
+        }
+        Err(err) => warn!("Discovery failed: {}", err),
+    }
+}
+
+/// Follow the bulbs' advertisements, e.g. to learn their new addresses
+/// from DHCP.
+async fn track_advertised_bulbs(pool: ConnectionPool) {
+    let listener = match RegistryListener::bind().await {
+        Ok(listener) => listener,
+        Err(err) => {
+            warn!("Unable to listen for the advertisements: {}", err);
+            return;
+        }
+    };
+    let registry = listener.registry();
+    let mut events = listener.subscribe();
+    tokio::spawn(async move {
+        if let Err(err) = listener.run().await {
+            warn!("Listening for the advertisements failed: {}", err);
+        }
+    });
+
+    loop {
+        match events.recv().await {
+            Ok(RegistryEvent::Added(bulb) | RegistryEvent::Changed(bulb)) => pool.add(bulb),
+            // The last known address is still the best guess.
+            Ok(RegistryEvent::Expired(_)) => {}
+            Err(RecvError::Lagged(_)) => {
+                for entry in registry.lock().unwrap().entries() {
+                    pool.add(entry.bulb.clone());
+                }
+            }
+            Err(RecvError::Closed) => break,
+        }
+    }
+}
+
+fn bulb_v1_routes(pool: ConnectionPool) -> Router {
     Router::new()
         .route("/on", post(handlers::power_on))
         .route("/off", post(handlers::power_off))
@@ -37,7 +114,20 @@ fn bulb_v1_routes() -> Router {
         .route("/brightness", post(handlers::brightness))
         .route("/temperature", post(handlers::temperature))
         .route("/color", post(handlers::color))
+        .route("/hsv", post(handlers::hsv))
+        .route("/adjust", post(handlers::adjust))
+        .route("/step", post(handlers::step))
+        .route("/default", post(handlers::save_default))
+        .route("/name", post(handlers::set_name))
+        .route(
+            "/timer",
+            get(handlers::get_timer)
+                .post(handlers::add_timer)
+                .delete(handlers::delete_timer),
+        )
         .route("/info", get(handlers::get_info))
+        .route("/capabilities", get(handlers::get_capabilities))
+        .with_state(pool)
 }
 
 fn bulb_v2_routes() -> Router {
@@ -87,9 +177,18 @@ async fn main() -> anyhow::Result<()> {
     let trace_layer = TraceLayer::new_for_http()
         .make_span_with(DefaultMakeSpan::new().level(tracing::Level::INFO))
         .on_response(DefaultOnResponse::new().level(tracing::Level::INFO));
+    let pool = ConnectionPool::new();
+    // The embedded config is served as is, but still needs its models.
+    let embedded_config = Assets::get("config.json")
+        .and_then(|config| serde_json::from_slice::<Value>(&config.data).ok());
+    if let Some(config) = config.as_ref().or(embedded_config.as_ref()) {
+        add_configured_bulbs(&pool, config);
+    }
+    tokio::spawn(add_discovered_bulbs(pool.clone()));
+    tokio::spawn(track_advertised_bulbs(pool.clone()));
     let routes = Router::new()
-        .merge(bulb_v1_routes())
-        .nest("/v1", bulb_v1_routes())
+        .merge(bulb_v1_routes(pool.clone()))
+        .nest("/v1", bulb_v1_routes(pool))
         .nest("/v2", bulb_v2_routes())
         .merge(config_routes(&config))
         .fallback_service(serve_assets)
@@ -99,10 +198,11 @@ async fn main() -> anyhow::Result<()> {
     let listener = tokio::net::TcpListener::bind(bind_addr).await?;
 
     if args.browse {
-        std::process::Command::new("xdg-open")
+        let mut browser = std::process::Command::new("xdg-open")
             .arg(format!("http://{bind_addr}"))
             .spawn()
             .expect("Failed to launch the web browser");
+        std::thread::spawn(move || browser.wait());
     }
 
     info!("Listening on http://{bind_addr}");
@@ -0,0 +1,98 @@
+use log::{info, warn};
+use serde_json::Value;
+use std::net::{IpAddr, SocketAddr};
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time;
+use tokio::try_join;
+
+use crate::{BulbCommands, BulbError, Command, Response, TimeoutKind, Timeouts};
+
+/// A connection opened by the bulb itself after `set_music`.
+///
+/// The bulb accepts any number of commands over it, but never
+/// responds to them nor sends any notifications, so the commands
+/// succeed as soon as they are sent and their responses are empty.
+#[derive(Debug)]
+pub struct MusicMode {
+    stream: TcpStream,
+    timeouts: Timeouts,
+    last_command_id: u16,
+}
+
+impl MusicMode {
+    /// Ask the bulb to connect back to `local_ip` and wait for it.
+    ///
+    /// `local_ip` needs to be reachable by the bulb, and `control` is
+    /// any regular connection to it.  The connections from anywhere
+    /// other than `bulb_ip` are rejected.
+    pub async fn start<C: BulbCommands>(
+        control: &mut C,
+        bulb_ip: IpAddr,
+        local_ip: IpAddr,
+        timeouts: Timeouts,
+    ) -> Result<Self, BulbError> {
+        let listener = TcpListener::bind(SocketAddr::new(local_ip, 0)).await?;
+        let addr = listener.local_addr()?;
+        info!("Waiting for the music mode connection on: {}", addr);
+
+        let accept_bulb = async {
+            loop {
+                let (stream, peer) = listener.accept().await.map_err(BulbError::Connect)?;
+                if peer.ip() == bulb_ip {
+                    return Ok((stream, peer));
+                }
+                warn!("Rejecting the music mode connection from: {}", peer);
+            }
+        };
+        let accept = async {
+            time::timeout(timeouts.connect, accept_bulb)
+                .await
+                .map_err(|_| BulbError::Timeout(TimeoutKind::Connect))?
+        };
+        let request = control.request(
+            "set_music",
+            vec![1.into(), addr.ip().to_string().into(), addr.port().into()],
+        );
+        let (_, (stream, peer)) = try_join!(request, accept)?;
+        info!("Music mode connection from: {}", peer);
+
+        Ok(Self {
+            stream,
+            timeouts,
+            last_command_id: 0,
+        })
+    }
+
+    /// Close the connection and tell the bulb to leave the music mode.
+    pub async fn stop<C: BulbCommands>(self, control: &mut C) -> Result<Response, BulbError> {
+        drop(self.stream);
+        control.request("set_music", vec![0.into()]).await
+    }
+}
+
+impl BulbCommands for MusicMode {
+    async fn request(&mut self, method: &str, params: Vec<Value>) -> Result<Response, BulbError> {
+        self.last_command_id = self.last_command_id.wrapping_add(1);
+        let command = Command {
+            id: self.last_command_id,
+            method: method.to_owned(),
+            params,
+        };
+
+        let payload = serde_json::to_string(&command)?;
+        info!("Sending (music mode): {}", payload);
+        let payload = payload + "\r\n";
+
+        time::timeout(
+            self.timeouts.write,
+            self.stream.write_all(payload.as_bytes()),
+        )
+        .await
+        .map_err(|_| BulbError::Timeout(TimeoutKind::Write))??;
+        Ok(Response {
+            id: command.id,
+            ..Default::default()
+        })
+    }
+}
@@ -0,0 +1,44 @@
+use serde::Deserialize;
+
+/// The direction of a `set_adjust` change.
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AdjustAction {
+    Increase,
+    Decrease,
+    /// Increase, wrapping around to the minimum after the maximum.
+    Circle,
+}
+
+impl AdjustAction {
+    pub fn action(&self) -> &'static str {
+        match self {
+            AdjustAction::Increase => "increase",
+            AdjustAction::Decrease => "decrease",
+            AdjustAction::Circle => "circle",
+        }
+    }
+}
+
+/// The value changed by `set_adjust`.
+///
+/// The color can only be changed with [`AdjustAction::Circle`].
+#[derive(Copy, Clone, Debug, PartialEq, Deserialize)]
+pub enum AdjustProp {
+    #[serde(rename = "bright")]
+    Brightness,
+    #[serde(rename = "ct")]
+    Temperature,
+    #[serde(rename = "color")]
+    Color,
+}
+
+impl AdjustProp {
+    pub fn prop(&self) -> &'static str {
+        match self {
+            AdjustProp::Brightness => "bright",
+            AdjustProp::Temperature => "ct",
+            AdjustProp::Color => "color",
+        }
+    }
+}
@@ -10,6 +10,12 @@ pub struct RangeError<T: Display> {
     max: T,
 }
 
+impl<T: Display> RangeError<T> {
+    pub(crate) fn new(value: T, min: T, max: T) -> Self {
+        Self { value, min, max }
+    }
+}
+
 pub trait BoundedRange<T: PartialOrd + Display> {
     const MIN: T;
     const MAX: T;
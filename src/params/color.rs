@@ -1,25 +1,130 @@
+use std::fmt;
 use std::num::ParseIntError;
+use std::str::FromStr;
 
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 use thiserror::Error;
 
+use super::color_names::COLOR_NAMES;
+
 #[derive(Error, Debug)]
 pub enum ColorError {
-    #[error("Hex string invalid as a color: {}", .0)]
+    #[error("String invalid as a color: {}", .0)]
     InvalidColor(String),
-    #[error("Unable to parse as hex: {}", .0)]
+    #[error("Unable to parse as a number: {}", .0)]
     Parse(#[from] ParseIntError),
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Color(pub(crate) u32);
 
 impl Color {
+    pub fn from_rgb(r: u8, g: u8, b: u8) -> Color {
+        Color(u32::from_be_bytes([0, r, g, b]))
+    }
+
+    pub fn rgb(&self) -> (u8, u8, u8) {
+        let [_, r, g, b] = self.0.to_be_bytes();
+        (r, g, b)
+    }
+
+    /// Parse `rrggbb` or `rgb`, optionally prefixed with `#`.
     pub fn from_hex(hex: &str) -> Result<Color, ColorError> {
-        match hex.len() {
-            6 => Ok(Color(u32::from_str_radix(hex, 16)?)),
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(ColorError::InvalidColor(hex.to_owned()));
+        }
+        match digits.len() {
+            6 => Ok(Color(u32::from_str_radix(digits, 16)?)),
+            3 => {
+                // Each digit is doubled, e.g. "f00" is "ff0000".
+                let value = u32::from_str_radix(digits, 16)?;
+                let [_, _, r, gb] = value.to_be_bytes();
+                let (g, b) = (gb >> 4, gb & 0xf);
+                Ok(Color::from_rgb(r * 0x11, g * 0x11, b * 0x11))
+            }
             _ => Err(ColorError::InvalidColor(hex.to_owned())),
         }
     }
+
+    /// Parse the decimal `0xRRGGBB` value, as reported by the bulbs in
+    /// the `rgb` property.
+    pub fn from_decimal(decimal: &str) -> Result<Color, ColorError> {
+        if decimal.is_empty() || !decimal.chars().all(|c| c.is_ascii_digit()) {
+            return Err(ColorError::InvalidColor(decimal.to_owned()));
+        }
+        match decimal.parse()? {
+            value @ 0..=0xffffff => Ok(Color(value)),
+            _ => Err(ColorError::InvalidColor(decimal.to_owned())),
+        }
+    }
+
+    /// Look up a CSS color name, ignoring the case.
+    pub fn from_name(name: &str) -> Option<Color> {
+        let name = name.to_ascii_lowercase();
+        COLOR_NAMES
+            .binary_search_by_key(&name.as_str(), |(name, _)| name)
+            .ok()
+            .map(|index| Color(COLOR_NAMES[index].1))
+    }
+
+    /// Parse `rgb(r, g, b)` with the decimal components.
+    fn from_rgb_function(color: &str) -> Option<Result<Color, ColorError>> {
+        let components = color.strip_prefix("rgb(")?.strip_suffix(')')?;
+        let components = components
+            .split(',')
+            .map(|component| component.trim().parse::<u8>())
+            .collect::<Result<Vec<_>, _>>();
+        Some(match components.as_deref() {
+            Ok(&[r, g, b]) => Ok(Color::from_rgb(r, g, b)),
+            Ok(_) => Err(ColorError::InvalidColor(color.to_owned())),
+            Err(err) => Err(ColorError::Parse(err.clone())),
+        })
+    }
+}
+
+/// Accepts `#rrggbb`, `rrggbb`, `#rgb`, the CSS color names,
+/// `rgb(r, g, b)` and the decimal values.
+///
+/// Six hex digits without `#` are always read as hex, not decimal,
+/// so the values reported by the bulbs need [`Color::from_decimal`].
+impl FromStr for Color {
+    type Err = ColorError;
+
+    fn from_str(color: &str) -> Result<Self, Self::Err> {
+        let color = color.trim();
+        let is_hex = color.len() == 6 && color.chars().all(|c| c.is_ascii_hexdigit());
+        if color.starts_with('#') || is_hex {
+            return Color::from_hex(color);
+        }
+        if let Some(color) = Color::from_rgb_function(color) {
+            return color;
+        }
+        if color.chars().all(|c| c.is_ascii_digit()) && !color.is_empty() {
+            return Color::from_decimal(color);
+        }
+        Color::from_name(color).ok_or_else(|| ColorError::InvalidColor(color.to_owned()))
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:06x}", self.0)
+    }
+}
+
+/// As `#rrggbb`.
+impl Serialize for Color {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let color = String::deserialize(deserializer)?;
+        color.parse().map_err(de::Error::custom)
+    }
 }
 
 #[cfg(test)]
@@ -35,9 +140,10 @@ mod tests {
         let result = Color::from_hex("fF0000");
         assert!(result.is_ok());
 
-        // In the future it might be interpretted as "ff0000".
         let result = Color::from_hex("f00");
-        assert!(result.is_err());
+        assert_eq!(result.unwrap(), Color(0xff0000));
+        let result = Color::from_hex("#1a2b3c");
+        assert_eq!(result.unwrap(), Color(0x1a2b3c));
 
         let result = Color::from_hex("fff0000");
         assert!(result.is_err());
@@ -45,5 +151,50 @@ mod tests {
         assert!(result.is_err());
         let result = Color::from_hex("ffoooo");
         assert!(result.is_err());
+        let result = Color::from_hex("+fffff");
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn from_str() {
+        let parse = |color: &str| color.parse::<Color>().unwrap();
+        assert_eq!(parse("#ff8000"), Color(0xff8000));
+        assert_eq!(parse("ff8000"), Color(0xff8000));
+        assert_eq!(parse("#f80"), Color(0xff8800));
+        assert_eq!(parse("RebeccaPurple"), Color(0x663399));
+        assert_eq!(parse("yellow"), Color(0xffff00));
+        assert_eq!(parse("rgb(255, 128, 0)"), Color(0xff8000));
+        assert_eq!(parse("16711680"), Color(0xff0000));
+        assert_eq!(parse("255"), Color(0x0000ff));
+
+        assert!("rgb(256, 0, 0)".parse::<Color>().is_err());
+        assert!("rgb(1, 2)".parse::<Color>().is_err());
+        assert!("16777216".parse::<Color>().is_err());
+        assert!("reddish".parse::<Color>().is_err());
+        assert!("".parse::<Color>().is_err());
+    }
+
+    #[test]
+    fn from_decimal() {
+        assert_eq!(Color::from_decimal("123456").unwrap(), Color(0x01e240));
+        assert_eq!(Color::from_decimal("16711680").unwrap(), Color(0xff0000));
+        assert!(Color::from_decimal("16777216").is_err());
+        assert!(Color::from_decimal("ff0000").is_err());
+        assert!(Color::from_decimal("").is_err());
+    }
+
+    #[test]
+    fn serde() {
+        let color = Color(0x01e240);
+        let json = serde_json::to_string(&color).unwrap();
+        assert_eq!(json, r##""#01e240""##);
+        assert_eq!(serde_json::from_str::<Color>(&json).unwrap(), color);
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Color(0xff).to_string(), "#0000ff");
+        let color = Color::from_rgb(18, 52, 86);
+        assert_eq!(color.to_string().parse::<Color>().unwrap(), color);
     }
 }
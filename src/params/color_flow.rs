@@ -0,0 +1,232 @@
+use thiserror::Error;
+
+use super::{BoundedRange, Brightness, Color, Temperature};
+
+#[derive(Error, Debug)]
+pub enum FlowError {
+    /// Rejected by the bulbs, or worse, run forever.
+    #[error("The color flow has no steps")]
+    Empty,
+}
+
+/// The duration of a single flow step in milliseconds.
+#[derive(Copy, Clone, Debug)]
+pub struct FlowDuration(pub(crate) u32);
+
+impl BoundedRange<u32> for FlowDuration {
+    const MIN: u32 = 50;
+    const MAX: u32 = u32::MAX;
+}
+
+impl From<u32> for FlowDuration {
+    fn from(value: u32) -> Self {
+        Self(value)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum FlowStep {
+    /// Change to a color, optionally with a brightness.
+    Color {
+        duration: FlowDuration,
+        color: Color,
+        brightness: Option<Brightness>,
+    },
+    /// Change to a color temperature, optionally with a brightness.
+    Temperature {
+        duration: FlowDuration,
+        temperature: Temperature,
+        brightness: Option<Brightness>,
+    },
+    /// Keep the current state.
+    Sleep { duration: FlowDuration },
+}
+
+impl FlowStep {
+    /// The `duration,mode,value,brightness` tuple.
+    fn expression(&self) -> String {
+        // Brightness of -1 means keeping the current one.
+        let brightness = |brightness: Option<Brightness>| match brightness {
+            Some(Brightness(brightness)) => i32::from(brightness),
+            None => -1,
+        };
+        match *self {
+            FlowStep::Color {
+                duration: FlowDuration(duration),
+                color: Color(color),
+                brightness: bright,
+            } => format!("{},1,{},{}", duration, color, brightness(bright)),
+            FlowStep::Temperature {
+                duration: FlowDuration(duration),
+                temperature: Temperature(temperature),
+                brightness: bright,
+            } => format!("{},2,{},{}", duration, temperature, brightness(bright)),
+            FlowStep::Sleep {
+                duration: FlowDuration(duration),
+            } => format!("{},7,0,0", duration),
+        }
+    }
+}
+
+/// What the bulb does once the flow ends.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum FlowEnd {
+    /// Go back to the state from before the flow.
+    Recover,
+    /// Stay in the last state of the flow.
+    Stay,
+    PowerOff,
+}
+
+impl FlowEnd {
+    pub fn action(&self) -> u8 {
+        match self {
+            FlowEnd::Recover => 0,
+            FlowEnd::Stay => 1,
+            FlowEnd::PowerOff => 2,
+        }
+    }
+}
+
+/// An animated sequence of states for `start_cf`.
+#[derive(Clone, Debug)]
+pub struct ColorFlow {
+    steps: Vec<FlowStep>,
+    /// How many times to run the steps, 0 meaning forever.
+    repeat: u16,
+    end: FlowEnd,
+}
+
+impl ColorFlow {
+    /// An empty flow running once.
+    pub fn new(end: FlowEnd) -> Self {
+        Self {
+            steps: vec![],
+            repeat: 1,
+            end,
+        }
+    }
+
+    pub fn step(mut self, step: FlowStep) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    pub fn color(
+        self,
+        duration: FlowDuration,
+        color: Color,
+        brightness: Option<Brightness>,
+    ) -> Self {
+        self.step(FlowStep::Color {
+            duration,
+            color,
+            brightness,
+        })
+    }
+
+    pub fn temperature(
+        self,
+        duration: FlowDuration,
+        temperature: Temperature,
+        brightness: Option<Brightness>,
+    ) -> Self {
+        self.step(FlowStep::Temperature {
+            duration,
+            temperature,
+            brightness,
+        })
+    }
+
+    pub fn sleep(self, duration: FlowDuration) -> Self {
+        self.step(FlowStep::Sleep { duration })
+    }
+
+    pub fn repeat(self, times: u16) -> Self {
+        Self {
+            repeat: times,
+            ..self
+        }
+    }
+
+    pub fn forever(self) -> Self {
+        self.repeat(0)
+    }
+
+    pub fn steps(&self) -> &[FlowStep] {
+        &self.steps
+    }
+
+    pub fn end(&self) -> FlowEnd {
+        self.end
+    }
+
+    /// Validate the flow before sending it.
+    pub fn check(&self) -> Result<(), FlowError> {
+        if self.steps.is_empty() {
+            return Err(FlowError::Empty);
+        }
+        Ok(())
+    }
+
+    /// The number of state changes before the flow ends, as expected
+    /// by `start_cf`.
+    pub fn count(&self) -> u32 {
+        u32::from(self.repeat) * self.steps.len() as u32
+    }
+
+    /// The comma-separated step tuples.
+    pub fn expression(&self) -> String {
+        self.steps
+            .iter()
+            .map(FlowStep::expression)
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn duration_new() {
+        assert!(FlowDuration::new(FlowDuration::MIN).is_ok());
+        assert!(FlowDuration::new(FlowDuration::MIN - 1).is_err());
+    }
+
+    #[test]
+    fn check() {
+        let flow = ColorFlow::new(FlowEnd::Stay);
+        assert!(matches!(flow.check(), Err(FlowError::Empty)));
+        let flow = flow.sleep(FlowDuration::new(500).unwrap());
+        assert!(flow.check().is_ok());
+    }
+
+    #[test]
+    fn expression() {
+        let flow = ColorFlow::new(FlowEnd::Stay)
+            .color(
+                FlowDuration::new(500).unwrap(),
+                Color::from_hex("ff0000").unwrap(),
+                Some(Brightness::new(100).unwrap()),
+            )
+            .sleep(FlowDuration::new(1000).unwrap())
+            .temperature(
+                FlowDuration::new(500).unwrap(),
+                Temperature::new(2700).unwrap(),
+                None,
+            )
+            .repeat(2);
+
+        assert_eq!(
+            flow.expression(),
+            "500,1,16711680,100,1000,7,0,0,500,2,2700,-1"
+        );
+        assert_eq!(flow.count(), 6);
+        assert_eq!(flow.end().action(), 1);
+
+        let flow = flow.forever();
+        assert_eq!(flow.count(), 0);
+    }
+}
@@ -0,0 +1,136 @@
+use serde::Serialize;
+
+use super::{BoundedRange, Color, RangeError};
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct Hue(pub(crate) u16);
+
+impl BoundedRange<u16> for Hue {
+    const MIN: u16 = 0;
+    const MAX: u16 = 359;
+}
+
+impl From<u16> for Hue {
+    fn from(value: u16) -> Self {
+        Self(value)
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct Saturation(pub(crate) u16);
+
+impl BoundedRange<u16> for Saturation {
+    const MIN: u16 = 0;
+    const MAX: u16 = 100;
+}
+
+impl From<u16> for Saturation {
+    fn from(value: u16) -> Self {
+        Self(value)
+    }
+}
+
+/// A color as hue and saturation, the value being controlled by
+/// the bulb's brightness.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct Hsv {
+    pub hue: Hue,
+    pub saturation: Saturation,
+}
+
+impl Hsv {
+    pub fn new(hue: u16, saturation: u16) -> Result<Self, RangeError<u16>> {
+        Ok(Self {
+            hue: Hue::new(hue)?,
+            saturation: Saturation::new(saturation)?,
+        })
+    }
+}
+
+impl From<Color> for Hsv {
+    fn from(color: Color) -> Self {
+        let (r, g, b) = color.rgb();
+        let (r, g, b) = (f64::from(r), f64::from(g), f64::from(b));
+        let max = r.max(g).max(b);
+        let delta = max - r.min(g).min(b);
+
+        let hue = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+        let saturation = if max == 0.0 { 0.0 } else { delta / max };
+
+        Self {
+            hue: Hue(hue.round() as u16 % 360),
+            saturation: Saturation((saturation * 100.0).round() as u16),
+        }
+    }
+}
+
+impl From<Hsv> for Color {
+    /// Convert at the full value.
+    fn from(hsv: Hsv) -> Self {
+        let Hsv {
+            hue: Hue(hue),
+            saturation: Saturation(saturation),
+        } = hsv;
+        let chroma = f64::from(saturation) / 100.0;
+        let sector = f64::from(hue) / 60.0;
+        let x = chroma * (1.0 - (sector.rem_euclid(2.0) - 1.0).abs());
+        let (r, g, b) = match hue / 60 {
+            0 => (chroma, x, 0.0),
+            1 => (x, chroma, 0.0),
+            2 => (0.0, chroma, x),
+            3 => (0.0, x, chroma),
+            4 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+        let channel = |value: f64| ((value + 1.0 - chroma) * 255.0).round() as u8;
+        Color::from_rgb(channel(r), channel(g), channel(b))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn new_within_range() {
+        assert!(Hsv::new(Hue::MAX, Saturation::MAX).is_ok());
+        assert!(Hsv::new(Hue::MIN, Saturation::MIN).is_ok());
+    }
+
+    #[test]
+    fn new_out_of_range() {
+        assert!(Hsv::new(Hue::MAX + 1, Saturation::MAX).is_err());
+        assert!(Hsv::new(Hue::MAX, Saturation::MAX + 1).is_err());
+    }
+
+    #[test]
+    fn from_color() {
+        let hsv = |hex| Hsv::from(Color::from_hex(hex).unwrap());
+        assert_eq!(hsv("ff0000"), Hsv::new(0, 100).unwrap());
+        assert_eq!(hsv("00ff00"), Hsv::new(120, 100).unwrap());
+        assert_eq!(hsv("0000ff"), Hsv::new(240, 100).unwrap());
+        assert_eq!(hsv("ff00ff"), Hsv::new(300, 100).unwrap());
+        assert_eq!(hsv("ffffff"), Hsv::new(0, 0).unwrap());
+        assert_eq!(hsv("ff8080"), Hsv::new(0, 50).unwrap());
+    }
+
+    #[test]
+    fn to_color() {
+        let color = |hue, saturation| Color::from(Hsv::new(hue, saturation).unwrap());
+        assert_eq!(color(0, 100), Color::from_hex("ff0000").unwrap());
+        assert_eq!(color(120, 100), Color::from_hex("00ff00").unwrap());
+        assert_eq!(color(240, 100), Color::from_hex("0000ff").unwrap());
+        assert_eq!(color(60, 0), Color::from_hex("ffffff").unwrap());
+
+        let hsv = Hsv::new(200, 50).unwrap();
+        assert_eq!(Hsv::from(Color::from(hsv)), hsv);
+    }
+}
@@ -1,5 +1,5 @@
 mod bounded_range;
-pub use bounded_range::BoundedRange;
+pub use bounded_range::{BoundedRange, RangeError};
 
 mod effect;
 pub use effect::*;
@@ -13,5 +13,18 @@ pub use temperature::*;
 mod percentage;
 pub use percentage::*;
 
+mod adjust;
+pub use adjust::*;
+
 mod color;
+mod color_names;
 pub use color::*;
+
+mod color_flow;
+pub use color_flow::*;
+
+mod hsv;
+pub use hsv::*;
+
+mod scene;
+pub use scene::*;
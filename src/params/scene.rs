@@ -0,0 +1,36 @@
+use super::{Brightness, Color, ColorFlow, Hsv, Temperature};
+
+/// A state set at once with `set_scene`, turning the bulb on if needed.
+#[derive(Clone, Debug)]
+pub enum Scene {
+    Color {
+        color: Color,
+        brightness: Brightness,
+    },
+    Hsv {
+        hsv: Hsv,
+        brightness: Brightness,
+    },
+    Temperature {
+        temperature: Temperature,
+        brightness: Brightness,
+    },
+    ColorFlow(ColorFlow),
+    /// Turn on at the given brightness and turn off after `minutes`.
+    AutoDelayOff {
+        brightness: Brightness,
+        minutes: u16,
+    },
+}
+
+impl Scene {
+    pub fn class(&self) -> &'static str {
+        match self {
+            Scene::Color { .. } => "color",
+            Scene::Hsv { .. } => "hsv",
+            Scene::Temperature { .. } => "ct",
+            Scene::ColorFlow(_) => "cf",
+            Scene::AutoDelayOff { .. } => "auto_delay_off",
+        }
+    }
+}
@@ -1,6 +1,8 @@
+use serde::Serialize;
+
 use super::BoundedRange;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
 pub struct Temperature(pub(crate) u16);
 
 impl BoundedRange<u16> for Temperature {
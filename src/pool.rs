@@ -0,0 +1,150 @@
+use serde_json::Value;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::sync::Mutex as AsyncMutex;
+
+use crate::{
+    Bulb, BulbCommands, BulbConnection, BulbError, Capabilities, ReconnectingConnection, Response,
+};
+
+#[derive(Clone, Debug)]
+struct Slot {
+    bulb: Bulb,
+    connection: Arc<AsyncMutex<ReconnectingConnection>>,
+}
+
+impl Slot {
+    fn new(bulb: Bulb) -> Self {
+        Self {
+            connection: Arc::new(AsyncMutex::new(ReconnectingConnection::new(bulb.clone()))),
+            bulb,
+        }
+    }
+}
+
+/// A handle to the connection to a bulb from the [`ConnectionPool`].
+///
+/// The commands from all the handles to the same bulb are sent
+/// concurrently over one connection, sharing its rate limiter, so the
+/// stale commands get coalesced.
+#[derive(Clone, Debug)]
+pub struct PooledConnection {
+    slot: Slot,
+}
+
+impl PooledConnection {
+    pub fn bulb(&self) -> &Bulb {
+        &self.slot.bulb
+    }
+
+    /// The lock is held only for reconnecting, never while waiting for
+    /// a command.
+    async fn connection(&self) -> Result<BulbConnection, BulbError> {
+        let mut connection = self.slot.connection.lock().await;
+        Ok(connection.connection().await?.clone())
+    }
+}
+
+impl BulbCommands for PooledConnection {
+    fn capabilities(&self) -> Option<&Capabilities> {
+        self.slot.bulb.capabilities()
+    }
+
+    async fn request(&mut self, method: &str, params: Vec<Value>) -> Result<Response, BulbError> {
+        let mut connection = self.connection().await?;
+        match connection.request(method, params.clone()).await {
+            Err(err) => {
+                self.slot
+                    .connection
+                    .lock()
+                    .await
+                    .recover(&connection, method, err)?;
+                self.connection().await?.request(method, params).await
+            }
+            result => result,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Slots {
+    by_addr: HashMap<SocketAddr, Slot>,
+    /// The latest addresses of the bulbs known by their ids.
+    by_id: HashMap<String, SocketAddr>,
+}
+
+/// Long-lived connections shared between the users of the same bulb.
+#[derive(Clone, Debug, Default)]
+pub struct ConnectionPool {
+    slots: Arc<Mutex<Slots>>,
+}
+
+impl ConnectionPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use the details of `bulb`, e.g. from discovery, for the
+    /// subsequent connections to it.
+    pub fn add(&self, bulb: Bulb) {
+        match bulb.info() {
+            Some(info) => self.add_as(info.id.clone(), bulb),
+            None => {
+                let mut slots = self.slots.lock().unwrap();
+                slots.by_addr.insert(bulb.addr(), Slot::new(bulb));
+            }
+        }
+    }
+
+    /// Like [`ConnectionPool::add`], also making the bulb findable by
+    /// `id`.  The connection to its previous address is dropped.
+    pub fn add_as(&self, id: String, bulb: Bulb) {
+        let mut slots = self.slots.lock().unwrap();
+        let addr = bulb.addr();
+        if let Some(previous) = slots.by_id.insert(id, addr) {
+            if previous != addr {
+                slots.by_addr.remove(&previous);
+            }
+        }
+        slots.by_addr.insert(addr, Slot::new(bulb));
+    }
+
+    /// The bulb last added with the given `id`.
+    pub fn find(&self, id: &str) -> Option<Bulb> {
+        let slots = self.slots.lock().unwrap();
+        let addr = slots.by_id.get(id)?;
+        slots.by_addr.get(addr).map(|slot| slot.bulb.clone())
+    }
+
+    /// The details stored for the bulb at `addr`, without connecting
+    /// to it.
+    pub fn bulb(&self, addr: SocketAddr) -> Option<Bulb> {
+        self.slots
+            .lock()
+            .unwrap()
+            .by_addr
+            .get(&addr)
+            .map(|slot| slot.bulb.clone())
+    }
+
+    /// Get a handle to the connection to `bulb`, connecting to it if
+    /// needed.
+    ///
+    /// Connections closed by the bulb in the meantime are replaced
+    /// with new ones.
+    pub async fn get(&self, bulb: &Bulb) -> Result<PooledConnection, BulbError> {
+        let slot = self
+            .slots
+            .lock()
+            .unwrap()
+            .by_addr
+            .entry(bulb.addr())
+            .or_insert_with(|| Slot::new(bulb.clone()))
+            .clone();
+
+        let pooled = PooledConnection { slot };
+        pooled.connection().await?;
+        Ok(pooled)
+    }
+}
@@ -0,0 +1,256 @@
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+
+use crate::params::{BoundedRange, Brightness, Color, Hue, Saturation, Temperature};
+use crate::{BulbError, ColorMode};
+
+/// The properties readable with `get_prop`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Property {
+    Power,
+    Bright,
+    Ct,
+    Rgb,
+    Hue,
+    Sat,
+    ColorMode,
+    Flowing,
+    /// The minutes left on the sleep timer.
+    Delayoff,
+    FlowParams,
+    MusicOn,
+    Name,
+    BgPower,
+    BgFlowing,
+    BgFlowParams,
+    BgCt,
+    /// The color mode of the background light.
+    BgLmode,
+    BgBright,
+    BgRgb,
+    BgHue,
+    BgSat,
+    /// The brightness of the night light.
+    NlBr,
+    /// 0 for the daylight mode, 1 for the night light.
+    ActiveMode,
+}
+
+impl Property {
+    pub const ALL: &'static [Property] = &[
+        Property::Power,
+        Property::Bright,
+        Property::Ct,
+        Property::Rgb,
+        Property::Hue,
+        Property::Sat,
+        Property::ColorMode,
+        Property::Flowing,
+        Property::Delayoff,
+        Property::FlowParams,
+        Property::MusicOn,
+        Property::Name,
+        Property::BgPower,
+        Property::BgFlowing,
+        Property::BgFlowParams,
+        Property::BgCt,
+        Property::BgLmode,
+        Property::BgBright,
+        Property::BgRgb,
+        Property::BgHue,
+        Property::BgSat,
+        Property::NlBr,
+        Property::ActiveMode,
+    ];
+
+    /// The name used by the protocol.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Property::Power => "power",
+            Property::Bright => "bright",
+            Property::Ct => "ct",
+            Property::Rgb => "rgb",
+            Property::Hue => "hue",
+            Property::Sat => "sat",
+            Property::ColorMode => "color_mode",
+            Property::Flowing => "flowing",
+            Property::Delayoff => "delayoff",
+            Property::FlowParams => "flow_params",
+            Property::MusicOn => "music_on",
+            Property::Name => "name",
+            Property::BgPower => "bg_power",
+            Property::BgFlowing => "bg_flowing",
+            Property::BgFlowParams => "bg_flow_params",
+            Property::BgCt => "bg_ct",
+            Property::BgLmode => "bg_lmode",
+            Property::BgBright => "bg_bright",
+            Property::BgRgb => "bg_rgb",
+            Property::BgHue => "bg_hue",
+            Property::BgSat => "bg_sat",
+            Property::NlBr => "nl_br",
+            Property::ActiveMode => "active_mode",
+        }
+    }
+
+    /// The counterpart of a main light property for the background
+    /// light.
+    pub fn background(&self) -> Option<Property> {
+        match self {
+            Property::Power => Some(Property::BgPower),
+            Property::Bright => Some(Property::BgBright),
+            Property::Ct => Some(Property::BgCt),
+            Property::Rgb => Some(Property::BgRgb),
+            Property::Hue => Some(Property::BgHue),
+            Property::Sat => Some(Property::BgSat),
+            Property::ColorMode => Some(Property::BgLmode),
+            Property::Flowing => Some(Property::BgFlowing),
+            Property::FlowParams => Some(Property::BgFlowParams),
+            _ => None,
+        }
+    }
+
+    /// Interpret a value read from the bulb.
+    ///
+    /// The bulbs reply with empty strings for the properties they
+    /// don't support.
+    pub fn decode(&self, value: &str) -> Result<PropertyValue, BulbError> {
+        let invalid = || BulbError::UnexpectedResult(format!("Invalid {self} value: {value}"));
+        if value.is_empty() {
+            return Ok(PropertyValue::Unsupported);
+        }
+        match self {
+            Property::Power | Property::BgPower => match value {
+                "on" => Ok(PropertyValue::Bool(true)),
+                "off" => Ok(PropertyValue::Bool(false)),
+                _ => Err(invalid()),
+            },
+            Property::Flowing | Property::BgFlowing | Property::MusicOn => match value {
+                "1" => Ok(PropertyValue::Bool(true)),
+                "0" => Ok(PropertyValue::Bool(false)),
+                _ => Err(invalid()),
+            },
+            Property::Name | Property::FlowParams | Property::BgFlowParams => {
+                Ok(PropertyValue::Text(value.to_owned()))
+            }
+            Property::Bright | Property::BgBright | Property::NlBr => {
+                self.bounded(value).map(PropertyValue::Brightness)
+            }
+            Property::Ct | Property::BgCt => self.bounded(value).map(PropertyValue::Temperature),
+            Property::Hue | Property::BgHue => self.bounded(value).map(PropertyValue::Hue),
+            Property::Sat | Property::BgSat => self.bounded(value).map(PropertyValue::Saturation),
+            Property::Rgb | Property::BgRgb => Color::from_decimal(value)
+                .map(PropertyValue::Color)
+                .map_err(|_| invalid()),
+            Property::ColorMode | Property::BgLmode => value
+                .parse()
+                .map(PropertyValue::ColorMode)
+                .map_err(|_| invalid()),
+            Property::Delayoff | Property::ActiveMode => value
+                .parse()
+                .map(PropertyValue::Number)
+                .map_err(|_| invalid()),
+        }
+    }
+
+    fn bounded<T: BoundedRange<u16> + From<u16>>(&self, value: &str) -> Result<T, BulbError> {
+        let value = value
+            .parse()
+            .map_err(|_| BulbError::UnexpectedResult(format!("Invalid {self} value: {value}")))?;
+        T::new(value)
+            .map_err(|err| BulbError::UnexpectedResult(format!("Invalid {self} value: {err}")))
+    }
+}
+
+impl fmt::Display for Property {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Property {
+    type Err = BulbError;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        Property::ALL
+            .iter()
+            .copied()
+            .find(|prop| prop.name() == name)
+            .ok_or_else(|| BulbError::UnexpectedResult(format!("Unknown property: {name}")))
+    }
+}
+
+impl Serialize for Property {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.name())
+    }
+}
+
+/// A decoded property value.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum PropertyValue {
+    /// Not supported by the bulb.
+    Unsupported,
+    Bool(bool),
+    Brightness(Brightness),
+    Temperature(Temperature),
+    Color(Color),
+    Hue(Hue),
+    Saturation(Saturation),
+    ColorMode(ColorMode),
+    Number(u32),
+    Text(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names() {
+        for prop in Property::ALL {
+            assert_eq!(prop.name().parse::<Property>().unwrap(), *prop);
+        }
+        assert!("brigth".parse::<Property>().is_err());
+    }
+
+    #[test]
+    fn decode() {
+        let decode = |prop: Property, value| prop.decode(value).unwrap();
+        assert_eq!(decode(Property::Power, "on"), PropertyValue::Bool(true));
+        assert_eq!(decode(Property::BgFlowing, "0"), PropertyValue::Bool(false));
+        assert_eq!(
+            decode(Property::Bright, "42"),
+            PropertyValue::Brightness(Brightness(42))
+        );
+        assert_eq!(
+            decode(Property::BgCt, "2700"),
+            PropertyValue::Temperature(Temperature(2700))
+        );
+        assert_eq!(
+            decode(Property::Rgb, "123456"),
+            PropertyValue::Color(Color(0x01e240))
+        );
+        assert_eq!(decode(Property::Hue, "359"), PropertyValue::Hue(Hue(359)));
+        assert_eq!(
+            decode(Property::Sat, "100"),
+            PropertyValue::Saturation(Saturation(100))
+        );
+        assert_eq!(
+            decode(Property::BgLmode, "2"),
+            PropertyValue::ColorMode(ColorMode::Temperature)
+        );
+        assert_eq!(decode(Property::Delayoff, "15"), PropertyValue::Number(15));
+        assert_eq!(
+            decode(Property::Name, "Desk"),
+            PropertyValue::Text("Desk".to_owned())
+        );
+        assert_eq!(decode(Property::NlBr, ""), PropertyValue::Unsupported);
+        assert!(Property::Power.decode("dimmed").is_err());
+        assert!(Property::Ct.decode("warm").is_err());
+        assert!(Property::Bright.decode("0").is_err());
+        assert!(Property::Hue.decode("360").is_err());
+        assert!(Property::ColorMode.decode("4").is_err());
+    }
+}
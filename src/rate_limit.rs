@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use tokio::time::{self, Duration, Instant};
+
+use crate::BulbError;
+
+/// The commands setting an absolute value, so that only the latest
+/// one waiting for its turn matters.
+const COALESCED_METHODS: &[&str] = &[
+    "set_bright",
+    "set_ct_abx",
+    "set_rgb",
+    "set_hsv",
+    "bg_set_bright",
+    "bg_set_ct_abx",
+    "bg_set_rgb",
+    "bg_set_hsv",
+];
+
+/// A snapshot of the [`RateLimiter`] for diagnostics.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RateLimiterState {
+    /// The commands that can be sent right away.
+    pub available: u32,
+    /// The commands waiting for their turn.
+    pub queued: usize,
+    /// The commands dropped in favor of newer ones.
+    pub coalesced: u64,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: u32,
+    last_refill: Instant,
+    queued: usize,
+    coalesced: u64,
+    next_ticket: u64,
+    /// The ticket of the newest waiting command per method.
+    latest: HashMap<String, u64>,
+}
+
+/// A token bucket keeping the commands within the bulb's quota.
+///
+/// The bulbs drop the connection when receiving more than
+/// 60 commands per minute.
+#[derive(Debug)]
+pub struct RateLimiter {
+    capacity: u32,
+    refill_interval: Duration,
+    bucket: Mutex<Bucket>,
+}
+
+impl Default for RateLimiter {
+    /// Allow bursts of 20 commands while keeping any minute within
+    /// the 60 commands quota.
+    fn default() -> Self {
+        Self::new(20, Duration::from_millis(1500))
+    }
+}
+
+impl RateLimiter {
+    /// Allow `capacity` commands at once, refilled by one every
+    /// `refill_interval`.
+    ///
+    /// Panics if either is zero, as no command would ever be sent.
+    pub fn new(capacity: u32, refill_interval: Duration) -> Self {
+        assert!(capacity > 0, "The rate limiter capacity must not be zero");
+        assert!(
+            !refill_interval.is_zero(),
+            "The rate limiter refill interval must not be zero"
+        );
+        Self {
+            capacity,
+            refill_interval,
+            bucket: Mutex::new(Bucket {
+                tokens: capacity,
+                last_refill: Instant::now(),
+                queued: 0,
+                coalesced: 0,
+                next_ticket: 0,
+                latest: HashMap::new(),
+            }),
+        }
+    }
+
+    fn refill(&self, bucket: &mut Bucket, now: Instant) {
+        let elapsed = now.saturating_duration_since(bucket.last_refill);
+        let new_tokens = elapsed.as_nanos() / self.refill_interval.as_nanos();
+        let missing = self.capacity - bucket.tokens;
+        if new_tokens >= missing as u128 {
+            bucket.tokens = self.capacity;
+            bucket.last_refill = now;
+        } else {
+            bucket.tokens += new_tokens as u32;
+            bucket.last_refill += self.refill_interval * new_tokens as u32;
+        }
+    }
+
+    pub fn state(&self) -> RateLimiterState {
+        let mut bucket = self.bucket.lock().unwrap();
+        self.refill(&mut bucket, Instant::now());
+        RateLimiterState {
+            available: bucket.tokens,
+            queued: bucket.queued,
+            coalesced: bucket.coalesced,
+        }
+    }
+
+    /// Wait until the `method` command may be sent.
+    ///
+    /// Fails with [`BulbError::Superseded`] if a newer command setting
+    /// the same value has been queued in the meantime.
+    pub async fn acquire(&self, method: &str) -> Result<(), BulbError> {
+        let coalesced = COALESCED_METHODS.contains(&method);
+        let ticket = {
+            let mut bucket = self.bucket.lock().unwrap();
+            bucket.queued += 1;
+            bucket.next_ticket += 1;
+            let ticket = bucket.next_ticket;
+            if coalesced {
+                bucket.latest.insert(method.to_owned(), ticket);
+            }
+            ticket
+        };
+        // Also leaves the queue if the caller stops waiting.
+        let _queued = Queued {
+            bucket: &self.bucket,
+            method,
+            ticket,
+        };
+
+        loop {
+            let next_token = {
+                let mut bucket = self.bucket.lock().unwrap();
+                self.refill(&mut bucket, Instant::now());
+                if coalesced && bucket.latest.get(method) != Some(&ticket) {
+                    bucket.coalesced += 1;
+                    return Err(BulbError::Superseded);
+                }
+                if bucket.tokens > 0 {
+                    bucket.tokens -= 1;
+                    return Ok(());
+                }
+                bucket.last_refill + self.refill_interval
+            };
+            time::sleep_until(next_token).await;
+        }
+    }
+}
+
+/// A command waiting in [`RateLimiter::acquire`].
+struct Queued<'a> {
+    bucket: &'a Mutex<Bucket>,
+    method: &'a str,
+    ticket: u64,
+}
+
+impl Drop for Queued<'_> {
+    fn drop(&mut self) {
+        let mut bucket = self.bucket.lock().unwrap();
+        bucket.queued -= 1;
+        if bucket.latest.get(self.method) == Some(&self.ticket) {
+            bucket.latest.remove(self.method);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[should_panic(expected = "refill interval")]
+    fn new_zero_interval() {
+        RateLimiter::new(1, Duration::ZERO);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn acquire_within_capacity() {
+        let limiter = RateLimiter::new(2, Duration::from_secs(1));
+        let start = Instant::now();
+
+        limiter.acquire("set_power").await.unwrap();
+        limiter.acquire("set_power").await.unwrap();
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        assert_eq!(limiter.state().available, 0);
+
+        limiter.acquire("set_power").await.unwrap();
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+
+        time::sleep(Duration::from_secs(10)).await;
+        assert_eq!(limiter.state().available, 2);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn acquire_coalesced() {
+        let limiter = RateLimiter::new(1, Duration::from_secs(1));
+        limiter.acquire("set_bright").await.unwrap();
+
+        let older = limiter.acquire("set_bright");
+        let other = limiter.acquire("set_power");
+        let newer = async {
+            time::sleep(Duration::from_millis(10)).await;
+            assert_eq!(limiter.state().queued, 2);
+            limiter.acquire("set_bright").await
+        };
+        let (older, other, newer) = tokio::join!(older, other, newer);
+
+        assert!(matches!(older, Err(BulbError::Superseded)));
+        assert!(other.is_ok());
+        assert!(newer.is_ok());
+
+        let state = limiter.state();
+        assert_eq!(state.queued, 0);
+        assert_eq!(state.coalesced, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn acquire_cancelled() {
+        let limiter = RateLimiter::new(1, Duration::from_secs(1));
+        limiter.acquire("set_bright").await.unwrap();
+
+        let waiting = time::timeout(Duration::from_millis(10), limiter.acquire("set_bright"));
+        assert!(waiting.await.is_err());
+        assert_eq!(limiter.state().queued, 0);
+    }
+}
@@ -0,0 +1,182 @@
+use log::{info, warn};
+use serde_json::Value;
+use std::cmp;
+use std::sync::Arc;
+use tokio::time::{self, Duration};
+
+use crate::{
+    Bulb, BulbCommands, BulbConnection, BulbError, Capabilities, RateLimiter, Response, TimeoutKind,
+};
+
+/// The delays between the consecutive connection attempts.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    /// The number of connection attempts before giving up.
+    pub attempts: u32,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self {
+            initial: Duration::from_millis(100),
+            max: Duration::from_secs(5),
+            attempts: 3,
+        }
+    }
+}
+
+/// Commands that can be safely sent again if it's unknown whether
+/// the bulb has executed them.
+fn is_idempotent(method: &str) -> bool {
+    let method = method.strip_prefix("bg_").unwrap_or(method);
+    // Unlike the other setters, it changes the value relative to the
+    // current one.
+    if method == "set_adjust" {
+        return false;
+    }
+    method == "get_prop" || method.starts_with("set_")
+}
+
+/// A [`BulbConnection`] reestablished whenever the bulb closes it.
+///
+/// The bulbs silently close the idle connections and lose them when
+/// power cycled.
+#[derive(Debug)]
+pub struct ReconnectingConnection {
+    bulb: Bulb,
+    connection: Option<BulbConnection>,
+    backoff: Backoff,
+    /// Shared by all the consecutive connections.
+    rate_limiter: Arc<RateLimiter>,
+}
+
+impl ReconnectingConnection {
+    /// Create the wrapper, connecting lazily on the first command.
+    pub fn new(bulb: Bulb) -> Self {
+        Self {
+            bulb,
+            connection: None,
+            backoff: Backoff::default(),
+            rate_limiter: Arc::default(),
+        }
+    }
+
+    pub fn with_backoff(self, backoff: Backoff) -> Self {
+        Self { backoff, ..self }
+    }
+
+    pub fn bulb(&self) -> &Bulb {
+        &self.bulb
+    }
+
+    pub fn rate_limiter(&self) -> &Arc<RateLimiter> {
+        &self.rate_limiter
+    }
+
+    /// The current connection, reconnecting if it's missing or closed.
+    pub async fn connection(&mut self) -> Result<&mut BulbConnection, BulbError> {
+        match self.connection.take() {
+            Some(connection) if !connection.is_closed() => Ok(self.connection.insert(connection)),
+            Some(_) => {
+                info!("Connection to {} closed, reconnecting", self.bulb.addr());
+                self.reconnect().await
+            }
+            None => self.reconnect().await,
+        }
+    }
+
+    /// Handle the failure of a command sent over `failed`, taken from
+    /// this wrapper.
+    ///
+    /// Forgets the connection if it's no longer usable, returning `Ok`
+    /// if the command should be sent again.
+    pub(crate) fn recover(
+        &mut self,
+        failed: &BulbConnection,
+        method: &str,
+        err: BulbError,
+    ) -> Result<(), BulbError> {
+        // A bulb not answering in time is likely gone without closing
+        // the connection, but it might have executed the command.
+        let timed_out = matches!(
+            err,
+            BulbError::Timeout(TimeoutKind::Write | TimeoutKind::Response)
+        );
+        if !err.is_disconnect() && !timed_out {
+            return Err(err);
+        }
+        // Another user might have reconnected in the meantime.
+        if self
+            .connection
+            .as_ref()
+            .is_some_and(|connection| connection.shares_socket(failed))
+        {
+            self.connection = None;
+        }
+        if timed_out || !is_idempotent(method) {
+            return Err(err);
+        }
+        warn!("Connection lost ({}), replaying: {}", err, method);
+        Ok(())
+    }
+
+    async fn reconnect(&mut self) -> Result<&mut BulbConnection, BulbError> {
+        let mut delay = self.backoff.initial;
+        let mut attempt = 1;
+        loop {
+            match self.bulb.connect().await {
+                Ok(mut connection) => {
+                    connection.set_rate_limiter(self.rate_limiter.clone());
+                    if attempt > 1 {
+                        info!(
+                            "Connected to {} after {} attempts",
+                            self.bulb.addr(),
+                            attempt
+                        );
+                    }
+                    return Ok(self.connection.insert(connection));
+                }
+                Err(err) if attempt < self.backoff.attempts => {
+                    warn!(
+                        "Connection attempt {} to {} failed, retrying in {:?}: {}",
+                        attempt,
+                        self.bulb.addr(),
+                        delay,
+                        err
+                    );
+                    time::sleep(delay).await;
+                    delay = cmp::min(delay * 2, self.backoff.max);
+                    attempt += 1;
+                }
+                Err(err) => {
+                    warn!(
+                        "Giving up connecting to {} after {} attempts: {}",
+                        self.bulb.addr(),
+                        attempt,
+                        err
+                    );
+                    return Err(err);
+                }
+            }
+        }
+    }
+}
+
+impl BulbCommands for ReconnectingConnection {
+    fn capabilities(&self) -> Option<&Capabilities> {
+        self.bulb.capabilities()
+    }
+
+    async fn request(&mut self, method: &str, params: Vec<Value>) -> Result<Response, BulbError> {
+        let mut connection = self.connection().await?.clone();
+        match connection.request(method, params.clone()).await {
+            Err(err) => {
+                self.recover(&connection, method, err)?;
+                self.connection().await?.request(method, params).await
+            }
+            result => result,
+        }
+    }
+}
@@ -0,0 +1,222 @@
+use log::{info, warn};
+use std::collections::BTreeMap;
+use std::io;
+use std::net::{IpAddr, Ipv4Addr};
+use std::sync::{Arc, Mutex};
+use tokio::net::UdpSocket;
+use tokio::sync::broadcast;
+use tokio::time::{self, Duration, Instant};
+
+use crate::discovery::{parse_notify, Advertisement, MULTICAST_ADDR};
+use crate::Bulb;
+
+/// How often the registry is checked for expired bulbs when the
+/// network is quiet.
+const EXPIRY_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Debug)]
+pub struct RegistryEntry {
+    pub bulb: Bulb,
+    pub last_seen: Instant,
+    pub max_age: Duration,
+}
+
+impl RegistryEntry {
+    fn expired(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.last_seen) > self.max_age
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum RegistryEvent {
+    Added(Bulb),
+    /// The bulb's address or advertised details have changed.
+    Changed(Bulb),
+    /// The bulb hasn't advertised itself within its `max-age`.
+    Expired(Bulb),
+}
+
+/// The bulbs seen in the network, keyed by their ids.
+#[derive(Debug, Default)]
+pub struct BulbRegistry {
+    entries: BTreeMap<String, RegistryEntry>,
+}
+
+impl BulbRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, id: &str) -> Option<&RegistryEntry> {
+        self.entries.get(id)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &RegistryEntry> {
+        self.entries.values()
+    }
+
+    /// Record a bulb seen at `now`.
+    pub fn update(&mut self, advertisement: Advertisement, now: Instant) -> Option<RegistryEvent> {
+        let Advertisement { bulb, max_age } = advertisement;
+        let id = bulb.info()?.id.clone();
+        let entry = RegistryEntry {
+            bulb: bulb.clone(),
+            last_seen: now,
+            max_age,
+        };
+
+        match self.entries.insert(id, entry) {
+            None => Some(RegistryEvent::Added(bulb)),
+            Some(previous) if previous.bulb != bulb => Some(RegistryEvent::Changed(bulb)),
+            Some(_) => None,
+        }
+    }
+
+    /// Remove the bulbs not seen for longer than their `max-age`.
+    pub fn expire(&mut self, now: Instant) -> Vec<RegistryEvent> {
+        let expired: Vec<String> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.expired(now))
+            .map(|(id, _)| id.clone())
+            .collect();
+        expired
+            .into_iter()
+            .filter_map(|id| self.entries.remove(&id))
+            .map(|entry| RegistryEvent::Expired(entry.bulb))
+            .collect()
+    }
+}
+
+/// Listens for the bulb advertisements and keeps a [`BulbRegistry`]
+/// up to date.
+pub struct RegistryListener {
+    socket: UdpSocket,
+    registry: Arc<Mutex<BulbRegistry>>,
+    events: broadcast::Sender<RegistryEvent>,
+}
+
+impl RegistryListener {
+    /// Join the Yeelight multicast group.
+    pub async fn bind() -> io::Result<Self> {
+        let socket = UdpSocket::bind((Ipv4Addr::UNSPECIFIED, MULTICAST_ADDR.port())).await?;
+        if let IpAddr::V4(group) = MULTICAST_ADDR.ip() {
+            socket.join_multicast_v4(group, Ipv4Addr::UNSPECIFIED)?;
+        }
+        Ok(Self::from_socket(socket))
+    }
+
+    /// Listen for the advertisements on an already bound socket.
+    pub fn from_socket(socket: UdpSocket) -> Self {
+        let (events, _) = broadcast::channel(16);
+        Self {
+            socket,
+            registry: Arc::default(),
+            events,
+        }
+    }
+
+    pub fn registry(&self) -> Arc<Mutex<BulbRegistry>> {
+        self.registry.clone()
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<RegistryEvent> {
+        self.events.subscribe()
+    }
+
+    fn emit(&self, event: RegistryEvent) {
+        info!("Registry event: {:?}", event);
+        // No subscribers is not an error, the registry is still updated.
+        let _ = self.events.send(event);
+    }
+
+    /// Process the advertisements until an I/O error occurs.
+    pub async fn run(self) -> io::Result<()> {
+        let mut buf = [0; 2048];
+        loop {
+            if let Ok(received) =
+                time::timeout(EXPIRY_CHECK_INTERVAL, self.socket.recv_from(&mut buf)).await
+            {
+                let (len, from) = received?;
+                match parse_notify(&String::from_utf8_lossy(&buf[..len])) {
+                    Ok(advertisement) => {
+                        let event = self
+                            .registry
+                            .lock()
+                            .unwrap()
+                            .update(advertisement, Instant::now());
+                        if let Some(event) = event {
+                            self.emit(event);
+                        }
+                    }
+                    Err(err) => {
+                        warn!("Invalid advertisement from {}, ignoring: {}", from, err);
+                    }
+                }
+            }
+
+            let expired = self.registry.lock().unwrap().expire(Instant::now());
+            for event in expired {
+                self.emit(event);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::BulbInfo;
+
+    fn advertisement(id: &str, addr: &str, max_age: u64) -> Advertisement {
+        let info = BulbInfo {
+            id: id.to_owned(),
+            model: "color".to_owned(),
+            fw_ver: "18".to_owned(),
+            support: vec![],
+            props: BTreeMap::new(),
+        };
+        Advertisement {
+            bulb: Bulb::with_info(addr.parse().unwrap(), info),
+            max_age: Duration::from_secs(max_age),
+        }
+    }
+
+    #[test]
+    fn update() {
+        let mut registry = BulbRegistry::new();
+        let now = Instant::now();
+
+        let first = advertisement("0x01", "192.168.1.10:55443", 60);
+        let event = registry.update(first.clone(), now);
+        assert_eq!(event, Some(RegistryEvent::Added(first.bulb.clone())));
+
+        let event = registry.update(first.clone(), now);
+        assert_eq!(event, None);
+
+        let moved = advertisement("0x01", "192.168.1.20:55443", 60);
+        let event = registry.update(moved.clone(), now);
+        assert_eq!(event, Some(RegistryEvent::Changed(moved.bulb.clone())));
+        assert_eq!(registry.get("0x01").unwrap().bulb, moved.bulb);
+        assert_eq!(registry.entries().count(), 1);
+    }
+
+    #[test]
+    fn expire() {
+        let mut registry = BulbRegistry::new();
+        let now = Instant::now();
+
+        let short = advertisement("0x01", "192.168.1.10:55443", 10);
+        let long = advertisement("0x02", "192.168.1.20:55443", 60);
+        registry.update(short.clone(), now);
+        registry.update(long, now);
+
+        let events = registry.expire(now + Duration::from_secs(10));
+        assert!(events.is_empty());
+
+        let events = registry.expire(now + Duration::from_secs(11));
+        assert_eq!(events, [RegistryEvent::Expired(short.bulb)]);
+        assert!(registry.get("0x01").is_none());
+        assert!(registry.get("0x02").is_some());
+    }
+}
@@ -0,0 +1,274 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use crate::params::{Brightness, Color, Hsv, Temperature};
+use crate::{BulbError, Channel, Property, PropertyValue};
+
+/// Which of the values a light currently displays.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    Rgb,
+    #[serde(rename = "ct")]
+    Temperature,
+    Hsv,
+}
+
+impl FromStr for ColorMode {
+    type Err = ();
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "1" => Ok(ColorMode::Rgb),
+            "2" => Ok(ColorMode::Temperature),
+            "3" => Ok(ColorMode::Hsv),
+            _ => Err(()),
+        }
+    }
+}
+
+/// The state of a single light of a bulb.
+///
+/// The values not supported by the bulb are `None`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct LightState {
+    pub power: bool,
+    pub brightness: Brightness,
+    pub temperature: Option<Temperature>,
+    pub color: Option<Color>,
+    pub hsv: Option<Hsv>,
+    pub color_mode: Option<ColorMode>,
+    /// Whether a color flow is running.
+    pub flowing: bool,
+}
+
+/// A snapshot of the whole bulb state, read with a single `get_prop`.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct BulbState {
+    #[serde(flatten)]
+    pub light: LightState,
+    /// The minutes until the sleep timer turns the bulb off, 0 if
+    /// it's not set.
+    pub delayoff: u16,
+    pub name: Option<String>,
+    /// The background light of a dual-light fixture.
+    pub background: Option<LightState>,
+}
+
+impl BulbState {
+    /// The props to request for [`BulbState::from_props`].
+    pub const PROPS: &'static [Property] = &[
+        Property::Power,
+        Property::Bright,
+        Property::Ct,
+        Property::Rgb,
+        Property::Hue,
+        Property::Sat,
+        Property::ColorMode,
+        Property::Flowing,
+        Property::Delayoff,
+        Property::Name,
+        Property::BgPower,
+        Property::BgBright,
+        Property::BgCt,
+        Property::BgRgb,
+        Property::BgHue,
+        Property::BgSat,
+        Property::BgLmode,
+        Property::BgFlowing,
+    ];
+
+    /// Parse the values of [`BulbState::PROPS`].
+    pub fn from_props(props: &BTreeMap<Property, String>) -> Result<Self, BulbError> {
+        let props = Props(props);
+        let background = match props.switch(Property::BgPower)? {
+            Some(power) => Some(props.light(&BACKGROUND, power)?),
+            None => None,
+        };
+        let power = props.switch(Property::Power)?.ok_or_else(|| {
+            BulbError::UnexpectedResult(format!("Missing {} value", Property::Power))
+        })?;
+        Ok(BulbState {
+            light: props.light(&MAIN, power)?,
+            delayoff: match props.decode(Property::Delayoff)? {
+                PropertyValue::Number(minutes) => u16::try_from(minutes).map_err(|_| {
+                    BulbError::UnexpectedResult(format!("Invalid delayoff value: {minutes}"))
+                })?,
+                _ => 0,
+            },
+            name: match props.decode(Property::Name)? {
+                PropertyValue::Text(name) => Some(name),
+                _ => None,
+            },
+            background,
+        })
+    }
+
+    /// The state with the `channel` light in place of the main one,
+    /// `None` if the bulb doesn't have it.
+    pub fn for_channel(self, channel: Channel) -> Option<Self> {
+        match channel {
+            Channel::Main => Some(self),
+            Channel::Background => Some(Self {
+                light: self.background?,
+                background: None,
+                ..self
+            }),
+        }
+    }
+}
+
+/// The props describing a single light.
+struct LightProps {
+    bright: Property,
+    ct: Property,
+    rgb: Property,
+    hue: Property,
+    sat: Property,
+    color_mode: Property,
+    flowing: Property,
+}
+
+const MAIN: LightProps = LightProps {
+    bright: Property::Bright,
+    ct: Property::Ct,
+    rgb: Property::Rgb,
+    hue: Property::Hue,
+    sat: Property::Sat,
+    color_mode: Property::ColorMode,
+    flowing: Property::Flowing,
+};
+
+const BACKGROUND: LightProps = LightProps {
+    bright: Property::BgBright,
+    ct: Property::BgCt,
+    rgb: Property::BgRgb,
+    hue: Property::BgHue,
+    sat: Property::BgSat,
+    color_mode: Property::BgLmode,
+    flowing: Property::BgFlowing,
+};
+
+struct Props<'a>(&'a BTreeMap<Property, String>);
+
+impl Props<'_> {
+    fn decode(&self, prop: Property) -> Result<PropertyValue, BulbError> {
+        let value = self.0.get(&prop).map(String::as_str).unwrap_or_default();
+        prop.decode(value)
+    }
+
+    fn switch(&self, prop: Property) -> Result<Option<bool>, BulbError> {
+        match self.decode(prop)? {
+            PropertyValue::Bool(value) => Ok(Some(value)),
+            _ => Ok(None),
+        }
+    }
+
+    fn light(&self, props: &LightProps, power: bool) -> Result<LightState, BulbError> {
+        let brightness = match self.decode(props.bright)? {
+            PropertyValue::Brightness(brightness) => brightness,
+            _ => {
+                return Err(BulbError::UnexpectedResult(format!(
+                    "Missing {} value",
+                    props.bright
+                )))
+            }
+        };
+        let temperature = match self.decode(props.ct)? {
+            PropertyValue::Temperature(temperature) => Some(temperature),
+            _ => None,
+        };
+        let color = match self.decode(props.rgb)? {
+            PropertyValue::Color(color) => Some(color),
+            _ => None,
+        };
+        let hsv = match (self.decode(props.hue)?, self.decode(props.sat)?) {
+            (PropertyValue::Hue(hue), PropertyValue::Saturation(saturation)) => {
+                Some(Hsv { hue, saturation })
+            }
+            _ => None,
+        };
+        let color_mode = match self.decode(props.color_mode)? {
+            PropertyValue::ColorMode(color_mode) => Some(color_mode),
+            _ => None,
+        };
+        Ok(LightState {
+            power,
+            brightness,
+            temperature,
+            color,
+            hsv,
+            color_mode,
+            flowing: self.switch(props.flowing)? == Some(true),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn props(values: &[&str]) -> BTreeMap<Property, String> {
+        BulbState::PROPS
+            .iter()
+            .copied()
+            .zip(values.iter().map(|value| value.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn from_props() {
+        let props = props(&[
+            "on", "42", "2700", "123456", "300", "70", "2", "0", "15", "Desk", "", "", "", "", "",
+            "", "", "",
+        ]);
+        let state = BulbState::from_props(&props).unwrap();
+        assert!(state.light.power);
+        assert_eq!(state.light.brightness, Brightness(42));
+        assert_eq!(state.light.temperature, Some(Temperature(2700)));
+        assert_eq!(state.light.color, Some(Color::from_rgb(0x01, 0xe2, 0x40)));
+        assert_eq!(state.light.hsv, Some(Hsv::new(300, 70).unwrap()));
+        assert_eq!(state.light.color_mode, Some(ColorMode::Temperature));
+        assert!(!state.light.flowing);
+        assert_eq!(state.delayoff, 15);
+        assert_eq!(state.name.as_deref(), Some("Desk"));
+        assert_eq!(state.background, None);
+        assert!(state.for_channel(Channel::Background).is_none());
+    }
+
+    #[test]
+    fn from_props_background() {
+        let props = props(&[
+            "off", "100", "4000", "", "", "", "2", "0", "0", "", "on", "10", "", "255", "", "",
+            "1", "1",
+        ]);
+        let state = BulbState::from_props(&props).unwrap();
+        assert!(!state.light.power);
+        assert_eq!(state.light.color, None);
+        assert_eq!(state.name, None);
+        let background = state.background.unwrap();
+        assert!(background.power);
+        assert_eq!(background.brightness, Brightness(10));
+        assert_eq!(background.color, Some(Color::from_rgb(0, 0, 255)));
+        assert_eq!(background.color_mode, Some(ColorMode::Rgb));
+        assert!(background.flowing);
+
+        let state = BulbState::from_props(&props).unwrap();
+        let state = state.for_channel(Channel::Background).unwrap();
+        assert_eq!(state.light, background);
+        assert_eq!(state.background, None);
+    }
+
+    #[test]
+    fn from_props_invalid() {
+        let mut values = props(&[
+            "on", "0", "2700", "", "", "", "2", "0", "0", "", "", "", "", "", "", "", "", "",
+        ]);
+        assert!(BulbState::from_props(&values).is_err());
+        values.insert(Property::Bright, "50".to_owned());
+        assert!(BulbState::from_props(&values).is_ok());
+        values.insert(Property::Power, "dimmed".to_owned());
+        assert!(BulbState::from_props(&values).is_err());
+    }
+}
@@ -1,3 +1,8 @@
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use tokio::io::AsyncReadExt;
+use tokio::net::TcpSocket;
+use tokio::time::{Duration, Instant};
 use tokio::try_join;
 
 use yeetlight::*;
@@ -12,7 +17,7 @@ async fn test_power() {
         .await
         .unwrap();
 
-    let bulb = Bulb::new(mock_listener.addr.ip());
+    let bulb = Bulb::from(mock_listener.addr);
     let mock_connection = mock_listener.accept();
     let bulb_connection = bulb.connect();
     let (mut mock_connection, mut bulb_connection) =
@@ -30,3 +35,932 @@ async fn test_power() {
     let (message, _response) = try_join!(message, response).unwrap();
     assert_eq!(message, expected);
 }
+
+#[tokio::test]
+async fn test_errors() {
+    let _ = simple_logger::init();
+
+    let (_mock_listener, mut mock_connection, mut bulb_connection) = mock::connect_pair().await;
+
+    let error = json!({"code": -1, "message": "unsupported method"});
+    let response = Response {
+        error: Some(error.as_object().unwrap().clone()),
+        ..Default::default()
+    };
+    let message = mock_connection.receive_and_respond(response);
+    let props = bulb_connection.get_props(&[Property::Power]);
+    let (message, props) = tokio::join!(message, props);
+    message.unwrap();
+    match props {
+        Err(BulbError::Bulb { code, message }) => {
+            assert_eq!(code, -1);
+            assert_eq!(message, "unsupported method");
+        }
+        props => panic!("Unexpected result: {props:?}"),
+    }
+
+    let error = json!({"code": -5000, "message": "general error"});
+    let response = Response {
+        error: Some(error.as_object().unwrap().clone()),
+        ..Default::default()
+    };
+    let message = mock_connection.receive_and_respond(response);
+    let response = bulb_connection.set_color(Color::from_hex("ff0000").unwrap(), Effect::Sudden);
+    let (message, response) = tokio::join!(message, response);
+    message.unwrap();
+    assert!(matches!(response, Err(BulbError::Bulb { code: -5000, .. })));
+
+    let response = Response {
+        result: Some(vec![Value::from("on")]),
+        ..Default::default()
+    };
+    let message = mock_connection.receive_and_respond(response);
+    let props = bulb_connection.get_props(&[Property::Power, Property::Bright]);
+    let (message, props) = tokio::join!(message, props);
+    message.unwrap();
+    assert!(matches!(props, Err(BulbError::UnexpectedResult(_))));
+}
+
+#[tokio::test]
+async fn test_garbled_message() {
+    let _ = simple_logger::init();
+
+    let (_mock_listener, mut mock_connection, mut bulb_connection) = mock::connect_pair().await;
+
+    // The pending command still gets its response.
+    let mock = async {
+        let message = mock_connection.receive_and_ignore().await?;
+        mock_connection.send_line(r#"{"id":1,"res"#).await?;
+        let response = Response {
+            id: 1,
+            ..Default::default()
+        };
+        mock_connection.respond(response).await?;
+        Ok(message)
+    };
+    let response = bulb_connection.set_power(true, Effect::Sudden);
+    try_join!(mock, response).unwrap();
+}
+
+#[tokio::test]
+async fn test_response_timeout() {
+    let _ = simple_logger::init();
+
+    let timeouts = Timeouts {
+        response: Duration::from_millis(200),
+        ..Default::default()
+    };
+    let (_mock_listener, mut mock_connection, mut bulb_connection) =
+        mock::connect_pair_with(|bulb| bulb.with_timeouts(timeouts)).await;
+    assert_eq!(bulb_connection.timeouts(), timeouts);
+
+    let start = Instant::now();
+    let message = mock_connection.receive_and_ignore();
+    let response = bulb_connection.set_power(true, Effect::Sudden);
+    let (message, response) = tokio::join!(message, response);
+    message.unwrap();
+    assert!(matches!(
+        response,
+        Err(BulbError::Timeout(TimeoutKind::Response))
+    ));
+    assert!(start.elapsed() >= timeouts.response);
+}
+
+#[tokio::test]
+async fn test_pool() {
+    let _ = simple_logger::init();
+
+    let mock_listener = mock::BulbListener::serve("127.0.0.1".parse().unwrap())
+        .await
+        .unwrap();
+
+    let bulb = Bulb::from(mock_listener.addr);
+    let pool = ConnectionPool::new();
+
+    // The details of the added bulbs are known without connecting.
+    assert!(pool.bulb(bulb.addr()).is_none());
+    let capabilities = Capabilities::for_model("color").unwrap();
+    pool.add(bulb.clone().with_capabilities(capabilities.clone()));
+    let added = pool.bulb(bulb.addr()).unwrap();
+    assert_eq!(added.capabilities(), Some(&capabilities));
+
+    let mock_connection = mock_listener.accept();
+    let pooled = pool.get(&bulb);
+    let (mut mock_connection, mut pooled) = try_join!(mock_connection, pooled).unwrap();
+
+    let response = pooled.set_power(true, Effect::Sudden);
+    let message = mock_connection.receive();
+    let (message, _response) = try_join!(message, response).unwrap();
+    assert_eq!(
+        message,
+        r#"{"id":1,"method":"set_power","params":["on","sudden",0]}"#
+    );
+
+    // Another user shares the same connection, as seen by the command ids.
+    let mut other = pool.get(&bulb).await.unwrap();
+    let response = other.set_power(false, Effect::Sudden);
+    let message = mock_connection.receive();
+    let (message, _response) = try_join!(message, response).unwrap();
+    assert_eq!(
+        message,
+        r#"{"id":2,"method":"set_power","params":["off","sudden",0]}"#
+    );
+    drop(pooled);
+    drop(other);
+
+    // A connection closed by the bulb gets replaced.
+    drop(mock_connection);
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let mock_connection = mock_listener.accept();
+    let pooled = pool.get(&bulb);
+    let (mut mock_connection, mut pooled) = try_join!(mock_connection, pooled).unwrap();
+
+    let response = pooled.set_power(true, Effect::Sudden);
+    let message = mock_connection.receive();
+    let (message, _response) = try_join!(message, response).unwrap();
+    assert_eq!(
+        message,
+        r#"{"id":1,"method":"set_power","params":["on","sudden",0]}"#
+    );
+}
+
+#[test]
+fn test_pool_ids() {
+    let pool = ConnectionPool::new();
+    let old: Bulb = "192.168.1.10".parse().unwrap();
+    let new: Bulb = "192.168.1.20".parse().unwrap();
+
+    assert!(pool.find("0x01").is_none());
+    pool.add_as("0x01".to_owned(), old.clone());
+    assert_eq!(pool.find("0x01").unwrap().addr(), old.addr());
+
+    // The bulb got a new address, and the old one is forgotten.
+    pool.add_as("0x01".to_owned(), new.clone());
+    assert_eq!(pool.find("0x01").unwrap().addr(), new.addr());
+    assert!(pool.bulb(old.addr()).is_none());
+}
+
+#[tokio::test]
+async fn test_pool_coalescing() {
+    let _ = simple_logger::init();
+
+    let mock_listener = mock::BulbListener::serve("127.0.0.1".parse().unwrap())
+        .await
+        .unwrap();
+
+    let bulb = Bulb::from(mock_listener.addr);
+    let pool = ConnectionPool::new();
+    let mock_connection = mock_listener.accept();
+    let pooled = pool.get(&bulb);
+    let (mut mock_connection, mut pooled) = try_join!(mock_connection, pooled).unwrap();
+
+    // Use up the burst allowed by the rate limiter.
+    for _ in 0..20 {
+        let response = pooled.set_power(true, Effect::Sudden);
+        let message = mock_connection.receive();
+        try_join!(message, response).unwrap();
+    }
+
+    // The users of the pool wait for their turn concurrently and only
+    // the latest brightness gets sent.
+    let set_brightness = |delay, brightness| {
+        let mut pooled = pooled.clone();
+        async move {
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            pooled
+                .set_brightness(Brightness::new(brightness).unwrap(), Effect::Sudden)
+                .await
+        }
+    };
+    let mock = async {
+        let message = mock_connection.receive_and_ignore().await?;
+        let response = Response {
+            id: 23,
+            ..Default::default()
+        };
+        mock_connection.respond(response).await?;
+        Ok::<_, BulbError>(message)
+    };
+    let (message, first, second, third) = tokio::join!(
+        mock,
+        set_brightness(0, 10),
+        set_brightness(10, 20),
+        set_brightness(20, 30),
+    );
+    // The superseded commands never reach the bulb, but still used up
+    // their ids.
+    assert_eq!(
+        message.unwrap(),
+        r#"{"id":23,"method":"set_bright","params":[30,"sudden",0]}"#
+    );
+    assert!(matches!(first, Err(BulbError::Superseded)));
+    assert!(matches!(second, Err(BulbError::Superseded)));
+    third.unwrap();
+}
+
+#[tokio::test]
+async fn test_reconnect() {
+    let _ = simple_logger::init();
+
+    let mock_listener = mock::BulbListener::serve("127.0.0.1".parse().unwrap())
+        .await
+        .unwrap();
+
+    let mut connection = ReconnectingConnection::new(Bulb::from(mock_listener.addr));
+    let mock_connection = mock_listener.accept();
+    let bulb_connection = async { connection.connection().await.map(|_| ()) };
+    let (mut mock_connection, _) = try_join!(mock_connection, bulb_connection).unwrap();
+
+    // The connection is lost before the bulb replies, so the idempotent
+    // command is sent again over a new one.
+    let mock = async {
+        let lost = mock_connection.receive_and_ignore().await?;
+        drop(mock_connection);
+        let mut mock_connection = mock_listener.accept().await?;
+        let replayed = mock_connection.receive().await?;
+        Ok((lost, replayed, mock_connection))
+    };
+    let response = connection.set_brightness(Brightness::new(50).unwrap(), Effect::Sudden);
+    let ((lost, replayed, mut mock_connection), _response) = try_join!(mock, response).unwrap();
+    let expected = r#"{"id":1,"method":"set_bright","params":[50,"sudden",0]}"#;
+    assert_eq!(lost, expected);
+    assert_eq!(replayed, expected);
+
+    // Non-idempotent commands are not replayed.
+    let mock = async {
+        let lost = mock_connection.receive_and_ignore().await?;
+        drop(mock_connection);
+        Ok::<_, BulbError>(lost)
+    };
+    let response = connection.adjust_brightness(Percentage::new(10).unwrap(), 0);
+    let (lost, response) = tokio::join!(mock, response);
+    assert_eq!(
+        lost.unwrap(),
+        r#"{"id":2,"method":"adjust_bright","params":[10,0]}"#
+    );
+    assert!(matches!(response, Err(BulbError::Disconnected)));
+
+    // Neither is the relative set_adjust, despite being a setter.
+    let mock = async {
+        let mut mock_connection = mock_listener.accept().await?;
+        let lost = mock_connection.receive_and_ignore().await?;
+        drop(mock_connection);
+        Ok::<_, BulbError>(lost)
+    };
+    let response = connection.set_adjust(AdjustAction::Increase, AdjustProp::Brightness);
+    let (lost, response) = tokio::join!(mock, response);
+    assert_eq!(
+        lost.unwrap(),
+        r#"{"id":1,"method":"set_adjust","params":["increase","bright"]}"#
+    );
+    assert!(matches!(response, Err(BulbError::Disconnected)));
+
+    // The next command reconnects.
+    let mock = async {
+        let mut mock_connection = mock_listener.accept().await?;
+        mock_connection.receive().await
+    };
+    let response = connection.set_power(true, Effect::Sudden);
+    let (message, _response) = try_join!(mock, response).unwrap();
+    assert_eq!(
+        message,
+        r#"{"id":1,"method":"set_power","params":["on","sudden",0]}"#
+    );
+}
+
+#[tokio::test]
+async fn test_reconnect_backoff() {
+    let _ = simple_logger::init();
+
+    // Find a port nobody listens on.
+    let mock_listener = mock::BulbListener::serve("127.0.0.1".parse().unwrap())
+        .await
+        .unwrap();
+    let addr = mock_listener.addr;
+    drop(mock_listener);
+
+    let backoff = Backoff {
+        initial: Duration::from_millis(20),
+        max: Duration::from_millis(30),
+        attempts: 3,
+    };
+    let mut connection = ReconnectingConnection::new(Bulb::from(addr)).with_backoff(backoff);
+
+    let start = Instant::now();
+    let response = connection.set_power(true, Effect::Sudden).await;
+    assert!(matches!(response, Err(BulbError::Connect(_))));
+    assert!(start.elapsed() >= Duration::from_millis(20 + 30));
+}
+
+#[tokio::test]
+async fn test_reconnect_after_timeout() {
+    let _ = simple_logger::init();
+
+    let mock_listener = mock::BulbListener::serve("127.0.0.1".parse().unwrap())
+        .await
+        .unwrap();
+
+    let timeouts = Timeouts {
+        response: Duration::from_millis(200),
+        ..Default::default()
+    };
+    let bulb = Bulb::from(mock_listener.addr).with_timeouts(timeouts);
+    let mut connection = ReconnectingConnection::new(bulb);
+    let mock_connection = mock_listener.accept();
+    let bulb_connection = async { connection.connection().await.map(|_| ()) };
+    let (mut mock_connection, _) = try_join!(mock_connection, bulb_connection).unwrap();
+
+    // The bulb stops answering without closing the connection, and
+    // the command is not replayed as it might have been executed.
+    let message = mock_connection.receive_and_ignore();
+    let response = connection.set_brightness(Brightness::new(50).unwrap(), Effect::Sudden);
+    let (message, response) = tokio::join!(message, response);
+    assert_eq!(
+        message.unwrap(),
+        r#"{"id":1,"method":"set_bright","params":[50,"sudden",0]}"#
+    );
+    assert!(matches!(
+        response,
+        Err(BulbError::Timeout(TimeoutKind::Response))
+    ));
+
+    // The next command uses a new connection.
+    let mock = async {
+        let mut mock_connection = mock_listener.accept().await?;
+        mock_connection.receive().await
+    };
+    let response = connection.set_power(true, Effect::Sudden);
+    let (message, _response) = try_join!(mock, response).unwrap();
+    assert_eq!(
+        message,
+        r#"{"id":1,"method":"set_power","params":["on","sudden",0]}"#
+    );
+    drop(mock_connection);
+}
+
+#[tokio::test]
+async fn test_notifications() {
+    let _ = simple_logger::init();
+
+    let (_mock_listener, mut mock_connection, mut bulb_connection) = mock::connect_pair().await;
+    let mut notifications = bulb_connection.subscribe();
+
+    // The notification arrives before the response it was caused by.
+    let mock = async {
+        let message = mock_connection.receive_and_ignore().await?;
+        mock_connection
+            .notify(json!({"power": "on", "bright": 10, "ct": "2700", "sparkle": "1"}))
+            .await?;
+        let response = Response {
+            id: 1,
+            result: Some(vec![Value::from("ok")]),
+            ..Default::default()
+        };
+        mock_connection.respond(response).await?;
+        Ok(message)
+    };
+    let response = bulb_connection.set_power(true, Effect::Sudden);
+    let (_message, response) = try_join!(mock, response).unwrap();
+    assert_eq!(response.result, Some(vec![Value::from("ok")]));
+
+    // Decoded like the values read with `get_prop`, skipping the
+    // unknown ones.
+    let notification = notifications.recv().await.unwrap();
+    assert_eq!(
+        notification.props,
+        BTreeMap::from([
+            (Property::Power, PropertyValue::Bool(true)),
+            (
+                Property::Bright,
+                PropertyValue::Brightness(Brightness::new(10).unwrap())
+            ),
+            (
+                Property::Ct,
+                PropertyValue::Temperature(Temperature::new(2700).unwrap())
+            ),
+        ])
+    );
+
+    // Other messages are not passed on.
+    mock_connection
+        .notify_method("toggle", json!({"power": "off"}))
+        .await
+        .unwrap();
+    mock_connection
+        .notify(json!({"power": "off"}))
+        .await
+        .unwrap();
+    let notification = notifications.recv().await.unwrap();
+    assert_eq!(
+        notification.props,
+        BTreeMap::from([(Property::Power, PropertyValue::Bool(false))])
+    );
+}
+
+#[tokio::test]
+async fn test_pipelined_commands() {
+    let _ = simple_logger::init();
+
+    let (_mock_listener, mut mock_connection, bulb_connection) = mock::connect_pair().await;
+
+    // All the commands are sent before any of them gets a response,
+    // and the responses arrive in the reverse order.
+    let mock = async {
+        let mut messages = vec![];
+        for _ in 0..3 {
+            messages.push(mock_connection.receive_and_ignore().await?);
+        }
+        for id in (1..=3).rev() {
+            let response = Response {
+                id,
+                result: Some(vec![Value::from(format!("result {id}"))]),
+                ..Default::default()
+            };
+            mock_connection.respond(response).await?;
+        }
+        Ok(messages)
+    };
+    let (mut power, mut brightness, mut temperature) = (
+        bulb_connection.clone(),
+        bulb_connection.clone(),
+        bulb_connection.clone(),
+    );
+    let power = power.set_power(true, Effect::Sudden);
+    let brightness = async {
+        // Keep the order of the commands deterministic.
+        tokio::task::yield_now().await;
+        brightness
+            .set_brightness(Brightness::new(50).unwrap(), Effect::Sudden)
+            .await
+    };
+    let temperature = async {
+        tokio::task::yield_now().await;
+        tokio::task::yield_now().await;
+        temperature
+            .set_temperature(Temperature::new(4000).unwrap(), Effect::Sudden)
+            .await
+    };
+    let (messages, power, brightness, temperature) =
+        try_join!(mock, power, brightness, temperature).unwrap();
+
+    let methods: Vec<String> = messages
+        .iter()
+        .map(|message| serde_json::from_str::<Command>(message).unwrap().method)
+        .collect();
+    assert_eq!(methods, ["set_power", "set_bright", "set_ct_abx"]);
+    assert_eq!(power.result, Some(vec![Value::from("result 1")]));
+    assert_eq!(brightness.result, Some(vec![Value::from("result 2")]));
+    assert_eq!(temperature.result, Some(vec![Value::from("result 3")]));
+}
+
+#[tokio::test]
+async fn test_hsv() {
+    let _ = simple_logger::init();
+
+    let (_mock_listener, mut mock_connection, mut bulb_connection) = mock::connect_pair().await;
+
+    let response = bulb_connection.set_hsv(Hsv::new(300, 70).unwrap(), Effect::Smooth(500));
+    let message = mock_connection.receive();
+    let (message, _response) = try_join!(message, response).unwrap();
+    assert_eq!(
+        message,
+        r#"{"id":1,"method":"set_hsv","params":[300,70,"smooth",500]}"#
+    );
+
+    let response = bulb_connection.get_hsv();
+    let message = mock_connection.receive_and_respond(Response {
+        result: Some(vec![Value::from("300"), Value::from("70")]),
+        ..Default::default()
+    });
+    let (message, hsv) = try_join!(message, response).unwrap();
+    assert_eq!(
+        message,
+        r#"{"id":2,"method":"get_prop","params":["hue","sat"]}"#
+    );
+    assert_eq!(hsv, Some(Hsv::new(300, 70).unwrap()));
+
+    // The bulbs without the HSV colors reply with empty strings.
+    let response = bulb_connection.get_hsv();
+    let message = mock_connection.receive_and_respond(Response {
+        result: Some(vec![Value::from(""), Value::from("")]),
+        ..Default::default()
+    });
+    let (_message, hsv) = try_join!(message, response).unwrap();
+    assert_eq!(hsv, None);
+}
+
+#[tokio::test]
+async fn test_color_flow() {
+    let _ = simple_logger::init();
+
+    let (_mock_listener, mut mock_connection, mut bulb_connection) = mock::connect_pair().await;
+
+    // Refused without sending anything.
+    let empty = ColorFlow::new(FlowEnd::Recover);
+    let result = bulb_connection.start_color_flow(&empty).await;
+    assert!(matches!(
+        result,
+        Err(BulbError::InvalidFlow(FlowError::Empty))
+    ));
+
+    let flow = ColorFlow::new(FlowEnd::Recover)
+        .temperature(
+            FlowDuration::new(1000).unwrap(),
+            Temperature::new(2700).unwrap(),
+            Some(Brightness::new(50).unwrap()),
+        )
+        .sleep(FlowDuration::new(500).unwrap())
+        .forever();
+    let response = bulb_connection.start_color_flow(&flow);
+    let expected = r#"{"id":1,"method":"start_cf","params":[0,0,"1000,2,2700,50,500,7,0,0"]}"#;
+    let message = mock_connection.receive();
+    let (message, _response) = try_join!(message, response).unwrap();
+    assert_eq!(message, expected);
+
+    let response = bulb_connection.stop_color_flow();
+    let expected = r#"{"id":2,"method":"stop_cf","params":[]}"#;
+    let message = mock_connection.receive();
+    let (message, _response) = try_join!(message, response).unwrap();
+    assert_eq!(message, expected);
+}
+
+#[tokio::test]
+async fn test_scene() {
+    let _ = simple_logger::init();
+
+    let (_mock_listener, mut mock_connection, mut bulb_connection) = mock::connect_pair().await;
+
+    let scenes = [
+        (
+            Scene::Color {
+                color: Color::from_hex("00ff00").unwrap(),
+                brightness: Brightness::new(30).unwrap(),
+            },
+            r#"["color",65280,30]"#,
+        ),
+        (
+            Scene::Hsv {
+                hsv: Hsv::new(300, 70).unwrap(),
+                brightness: Brightness::new(40).unwrap(),
+            },
+            r#"["hsv",300,70,40]"#,
+        ),
+        (
+            Scene::Temperature {
+                temperature: Temperature::new(2700).unwrap(),
+                brightness: Brightness::new(50).unwrap(),
+            },
+            r#"["ct",2700,50]"#,
+        ),
+        (
+            Scene::ColorFlow(
+                ColorFlow::new(FlowEnd::PowerOff).sleep(FlowDuration::new(60000).unwrap()),
+            ),
+            r#"["cf",1,2,"60000,7,0,0"]"#,
+        ),
+        (
+            Scene::AutoDelayOff {
+                brightness: Brightness::new(10).unwrap(),
+                minutes: 15,
+            },
+            r#"["auto_delay_off",10,15]"#,
+        ),
+    ];
+    for (id, (scene, params)) in (1..).zip(scenes) {
+        let response = bulb_connection.set_scene(&scene);
+        let message = mock_connection.receive();
+        let (message, _response) = try_join!(message, response).unwrap();
+        assert_eq!(
+            message,
+            format!(r#"{{"id":{id},"method":"set_scene","params":{params}}}"#)
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_state() {
+    let _ = simple_logger::init();
+
+    let (_mock_listener, mut mock_connection, mut bulb_connection) = mock::connect_pair().await;
+
+    // Both lights are read at once, each prop requested once.
+    let values = [
+        "off", "100", "4000", "", "", "", "2", "0", "0", "", "on", "10", "", "255", "", "", "1",
+        "0",
+    ];
+    let response = bulb_connection.get_state();
+    let message = mock_connection.receive_and_respond(Response {
+        result: Some(values.iter().map(|&value| Value::from(value)).collect()),
+        ..Default::default()
+    });
+    let (message, state) = try_join!(message, response).unwrap();
+    assert_eq!(
+        message,
+        r#"{"id":1,"method":"get_prop","params":["power","bright","ct","rgb","hue","sat","color_mode","flowing","delayoff","name","bg_power","bg_bright","bg_ct","bg_rgb","bg_hue","bg_sat","bg_lmode","bg_flowing"]}"#
+    );
+
+    let state = state.for_channel(Channel::Background).unwrap();
+    assert!(state.light.power);
+    assert_eq!(state.light.brightness, Brightness::new(10).unwrap());
+    assert_eq!(state.light.color, Some(Color::from_rgb(0, 0, 255)));
+    assert_eq!(state.background, None);
+}
+
+#[tokio::test]
+async fn test_background_channel() {
+    let _ = simple_logger::init();
+
+    let (_mock_listener, mut mock_connection, mut bulb_connection) = mock::connect_pair().await;
+
+    let mut background = bulb_connection.on(Channel::Background);
+    let response = background.set_power(true, Effect::Sudden);
+    let message = mock_connection.receive();
+    let (message, _response) = try_join!(message, response).unwrap();
+    assert_eq!(
+        message,
+        r#"{"id":1,"method":"bg_set_power","params":["on","sudden",0]}"#
+    );
+
+    let mut background = bulb_connection.on(Channel::Background);
+    let response = background.get_props_map(&[Property::Power, Property::ColorMode]);
+    let message = mock_connection.receive_and_respond(Response {
+        result: Some(vec!["on".into(), "2".into()]),
+        ..Default::default()
+    });
+    let (props, message) = try_join!(response, message).unwrap();
+    assert_eq!(
+        message,
+        r#"{"id":2,"method":"get_prop","params":["bg_power","bg_lmode"]}"#
+    );
+    assert_eq!(props[&Property::Power], "on");
+    assert_eq!(props[&Property::ColorMode], "2");
+}
+
+#[tokio::test]
+async fn test_toggle() {
+    let _ = simple_logger::init();
+
+    let (_mock_listener, mut mock_connection, mut bulb_connection) = mock::connect_pair().await;
+
+    let response = bulb_connection.toggle();
+    let message = mock_connection.receive();
+    let (message, _response) = try_join!(message, response).unwrap();
+    assert_eq!(message, r#"{"id":1,"method":"toggle","params":[]}"#);
+
+    let mut background = bulb_connection.on(Channel::Background);
+    let response = background.toggle();
+    let message = mock_connection.receive();
+    let (message, _response) = try_join!(message, response).unwrap();
+    assert_eq!(message, r#"{"id":2,"method":"bg_toggle","params":[]}"#);
+
+    let response = bulb_connection.toggle_device();
+    let message = mock_connection.receive();
+    let (message, _response) = try_join!(message, response).unwrap();
+    assert_eq!(message, r#"{"id":3,"method":"dev_toggle","params":[]}"#);
+}
+
+#[tokio::test]
+async fn test_adjust() {
+    let _ = simple_logger::init();
+
+    let (_mock_listener, mut mock_connection, mut bulb_connection) = mock::connect_pair().await;
+
+    let response = bulb_connection.adjust_temperature(Percentage::new(-20).unwrap(), 500);
+    let message = mock_connection.receive();
+    let (message, _response) = try_join!(message, response).unwrap();
+    assert_eq!(
+        message,
+        r#"{"id":1,"method":"adjust_ct","params":[-20,500]}"#
+    );
+
+    let response = bulb_connection.adjust_color(Percentage::new(10).unwrap(), 0);
+    let message = mock_connection.receive();
+    let (message, _response) = try_join!(message, response).unwrap();
+    assert_eq!(
+        message,
+        r#"{"id":2,"method":"adjust_color","params":[10,0]}"#
+    );
+
+    let response = bulb_connection.set_adjust(AdjustAction::Circle, AdjustProp::Color);
+    let message = mock_connection.receive();
+    let (message, _response) = try_join!(message, response).unwrap();
+    assert_eq!(
+        message,
+        r#"{"id":3,"method":"set_adjust","params":["circle","color"]}"#
+    );
+}
+
+#[tokio::test]
+async fn test_sleep_timer() {
+    let _ = simple_logger::init();
+
+    let (_mock_listener, mut mock_connection, mut bulb_connection) = mock::connect_pair().await;
+
+    let response = bulb_connection.add_sleep_timer(30);
+    let message = mock_connection.receive();
+    let (message, _response) = try_join!(message, response).unwrap();
+    assert_eq!(message, r#"{"id":1,"method":"cron_add","params":[0,30]}"#);
+
+    let timer = bulb_connection.get_sleep_timer();
+    let message = mock_connection.receive_and_respond(Response {
+        result: Some(vec![json!({"type": 0, "delay": 29, "mix": 0})]),
+        ..Default::default()
+    });
+    let (timer, message) = try_join!(timer, message).unwrap();
+    assert_eq!(message, r#"{"id":2,"method":"cron_get","params":[0]}"#);
+    assert_eq!(timer, Some(SleepTimer { minutes: 29 }));
+
+    let response = bulb_connection.delete_sleep_timer();
+    let message = mock_connection.receive();
+    let (message, _response) = try_join!(message, response).unwrap();
+    assert_eq!(message, r#"{"id":3,"method":"cron_del","params":[0]}"#);
+
+    let timer = bulb_connection.get_sleep_timer();
+    let message = mock_connection.receive_and_respond(Response {
+        result: Some(vec![]),
+        ..Default::default()
+    });
+    let (timer, _message) = try_join!(timer, message).unwrap();
+    assert_eq!(timer, None);
+}
+
+#[tokio::test]
+async fn test_set_default() {
+    let _ = simple_logger::init();
+
+    let (_mock_listener, mut mock_connection, mut bulb_connection) = mock::connect_pair().await;
+
+    let response = bulb_connection.set_default();
+    let message = mock_connection.receive();
+    let (message, _response) = try_join!(message, response).unwrap();
+    assert_eq!(message, r#"{"id":1,"method":"set_default","params":[]}"#);
+
+    let mut background = bulb_connection.on(Channel::Background);
+    let response = background.set_default();
+    let message = mock_connection.receive();
+    let (message, _response) = try_join!(message, response).unwrap();
+    assert_eq!(message, r#"{"id":2,"method":"bg_set_default","params":[]}"#);
+}
+
+#[tokio::test]
+async fn test_name() {
+    let _ = simple_logger::init();
+
+    let (_mock_listener, mut mock_connection, mut bulb_connection) = mock::connect_pair().await;
+
+    let response = bulb_connection.set_name("Desk lamp");
+    let message = mock_connection.receive();
+    let (message, _response) = try_join!(message, response).unwrap();
+    assert_eq!(
+        message,
+        r#"{"id":1,"method":"set_name","params":["Desk lamp"]}"#
+    );
+
+    let name = bulb_connection.get_name();
+    let message = mock_connection.receive_and_respond(Response {
+        result: Some(vec!["Desk lamp".into()]),
+        ..Default::default()
+    });
+    let (name, message) = try_join!(name, message).unwrap();
+    assert_eq!(message, r#"{"id":2,"method":"get_prop","params":["name"]}"#);
+    assert_eq!(name.as_deref(), Some("Desk lamp"));
+
+    let name = bulb_connection.get_name();
+    let message = mock_connection.receive_and_respond(Response {
+        result: Some(vec!["".into()]),
+        ..Default::default()
+    });
+    let (name, _message) = try_join!(name, message).unwrap();
+    assert_eq!(name, None);
+}
+
+#[tokio::test]
+async fn test_music_mode() {
+    let _ = simple_logger::init();
+
+    let (mock_listener, mut mock_connection, mut bulb_connection) = mock::connect_pair().await;
+
+    let music = MusicMode::start(
+        &mut bulb_connection,
+        mock_listener.addr.ip(),
+        "127.0.0.1".parse().unwrap(),
+        Timeouts::default(),
+    );
+    let mock_music = async {
+        let message = mock_connection.receive().await?;
+        let command: Value = serde_json::from_str(&message)?;
+        assert_eq!(command["method"], "set_music");
+        assert_eq!(command["params"][0], 1);
+        assert_eq!(command["params"][1], "127.0.0.1");
+        let port = command["params"][2].as_u64().unwrap() as u16;
+
+        // Anyone else connecting first gets rejected.
+        let intruder = TcpSocket::new_v4()?;
+        intruder.bind(([127, 0, 0, 2], 0).into())?;
+        let intruder = intruder.connect(([127, 0, 0, 1], port).into()).await?;
+
+        let mock_music = mock::BulbConnection::connect(([127, 0, 0, 1], port).into()).await?;
+        Ok::<_, BulbError>((mock_music, intruder))
+    };
+    let (mut music, (mut mock_music, mut intruder)) = try_join!(music, mock_music).unwrap();
+    let mut buffer = [0; 1];
+    assert_eq!(intruder.read(&mut buffer).await.unwrap(), 0);
+
+    // Nothing is ever received back in the music mode.
+    for (id, brightness) in (1..).zip([10, 20, 30]) {
+        music
+            .set_brightness(Brightness::new(brightness).unwrap(), Effect::Sudden)
+            .await
+            .unwrap();
+        assert_eq!(
+            mock_music.receive_and_ignore().await.unwrap(),
+            format!(r#"{{"id":{id},"method":"set_bright","params":[{brightness},"sudden",0]}}"#)
+        );
+    }
+
+    let stop = music.stop(&mut bulb_connection);
+    let message = mock_connection.receive();
+    let (_response, message) = try_join!(stop, message).unwrap();
+    assert_eq!(message, r#"{"id":2,"method":"set_music","params":[0]}"#);
+}
+
+#[tokio::test]
+async fn test_unsupported() {
+    let _ = simple_logger::init();
+
+    let (_mock_listener, mut mock_connection, mut bulb_connection) =
+        mock::connect_pair_with(|bulb| {
+            bulb.with_capabilities(Capabilities::for_model("mono").unwrap())
+        })
+        .await;
+
+    // Refused without sending anything.
+    let result = bulb_connection
+        .set_color(Color::from_hex("ff0000").unwrap(), Effect::Sudden)
+        .await;
+    assert!(matches!(result, Err(BulbError::Unsupported(method)) if method == "set_rgb"));
+
+    let response = bulb_connection.set_brightness(Brightness::new(50).unwrap(), Effect::Sudden);
+    let message = mock_connection.receive();
+    let (message, _response) = try_join!(message, response).unwrap();
+    assert_eq!(
+        message,
+        r#"{"id":1,"method":"set_bright","params":[50,"sudden",0]}"#
+    );
+}
+
+#[tokio::test]
+async fn test_temperature_range() {
+    let _ = simple_logger::init();
+
+    let (_mock_listener, mut mock_connection, mut bulb_connection) =
+        mock::connect_pair_with(|bulb| {
+            bulb.with_capabilities(Capabilities::for_model("ceiling").unwrap())
+        })
+        .await;
+
+    // Refused without sending anything.
+    let result = bulb_connection
+        .set_temperature(Temperature::new(1700).unwrap(), Effect::Sudden)
+        .await;
+    match result {
+        Err(err @ BulbError::OutOfRange(_)) => {
+            assert!(err.to_string().contains("[2700..6500]"), "{err}");
+        }
+        result => panic!("Unexpected result: {result:?}"),
+    }
+
+    let response = bulb_connection.set_temperature(Temperature::new(2700).unwrap(), Effect::Sudden);
+    let message = mock_connection.receive();
+    let (message, _response) = try_join!(message, response).unwrap();
+    assert_eq!(
+        message,
+        r#"{"id":1,"method":"set_ct_abx","params":[2700,"sudden",0]}"#
+    );
+}
+
+#[tokio::test]
+async fn test_background_temperature_range() {
+    let _ = simple_logger::init();
+
+    let (_mock_listener, mut mock_connection, mut bulb_connection) =
+        mock::connect_pair_with(|bulb| {
+            bulb.with_capabilities(Capabilities::for_model("ceiling4").unwrap())
+        })
+        .await;
+
+    // Too warm for the main light, but not for the background one.
+    let result = bulb_connection
+        .set_temperature(Temperature::new(2000).unwrap(), Effect::Sudden)
+        .await;
+    assert!(matches!(result, Err(BulbError::OutOfRange(_))));
+
+    let mut background = bulb_connection.on(Channel::Background);
+    let response = background.set_temperature(Temperature::new(2000).unwrap(), Effect::Sudden);
+    let message = mock_connection.receive();
+    let (message, _response) = try_join!(message, response).unwrap();
+    assert_eq!(
+        message,
+        r#"{"id":1,"method":"bg_set_ct_abx","params":[2000,"sudden",0]}"#
+    );
+}
@@ -0,0 +1,109 @@
+use std::net::SocketAddr;
+use tokio::net::UdpSocket;
+use tokio::time::Duration;
+
+use yeetlight::*;
+
+fn reply(id: &str, addr: SocketAddr) -> String {
+    message("HTTP/1.1 200 OK", id, addr)
+}
+
+fn notify(id: &str, addr: SocketAddr) -> String {
+    message("NOTIFY * HTTP/1.1", id, addr)
+}
+
+fn message(status: &str, id: &str, addr: SocketAddr) -> String {
+    format!(
+        "{status}\r\n\
+         Cache-Control: max-age=3600\r\n\
+         Location: yeelight://{addr}\r\n\
+         id: {id}\r\n\
+         model: mono\r\n\
+         fw_ver: 45\r\n\
+         support: get_prop set_power toggle set_bright\r\n\
+         power: off\r\n\
+         bright: 50\r\n"
+    )
+}
+
+#[tokio::test]
+async fn test_discover() {
+    let _ = simple_logger::init();
+
+    let responder = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let responder_addr = responder.local_addr().unwrap();
+
+    let mock = async {
+        let mut buf = [0; 1024];
+        let (len, client) = responder.recv_from(&mut buf).await.unwrap();
+        let request = String::from_utf8_lossy(&buf[..len]).into_owned();
+
+        let first: SocketAddr = "127.0.0.1:55443".parse().unwrap();
+        let second: SocketAddr = "127.0.0.2:55443".parse().unwrap();
+        for packet in [
+            reply("0x01", first),
+            // Bulbs tend to reply more than once.
+            reply("0x01", first),
+            "garbage".to_owned(),
+            reply("0x02", second),
+        ] {
+            responder.send_to(packet.as_bytes(), client).await.unwrap();
+        }
+        request
+    };
+    let discovery = discover_at(responder_addr, Duration::from_millis(300));
+    let (request, bulbs) = tokio::join!(mock, discovery);
+
+    assert!(request.starts_with("M-SEARCH * HTTP/1.1\r\n"));
+    assert!(request.contains("ST: wifi_bulb\r\n"));
+
+    let bulbs = bulbs.unwrap();
+    let ids: Vec<&str> = bulbs
+        .iter()
+        .map(|bulb| bulb.info().unwrap().id.as_str())
+        .collect();
+    assert_eq!(ids, ["0x01", "0x02"]);
+    assert_eq!(bulbs[1].addr(), "127.0.0.2:55443".parse().unwrap());
+    assert_eq!(bulbs[0].info().unwrap().props["bright"], "50");
+}
+
+#[tokio::test]
+async fn test_registry_listener() {
+    let _ = simple_logger::init();
+
+    let socket = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let listener_addr = socket.local_addr().unwrap();
+    let listener = RegistryListener::from_socket(socket);
+    let registry = listener.registry();
+    let mut events = listener.subscribe();
+    tokio::spawn(listener.run());
+
+    let sender = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+    let first: SocketAddr = "127.0.0.1:55443".parse().unwrap();
+    let moved: SocketAddr = "127.0.0.3:55443".parse().unwrap();
+    for packet in [
+        notify("0x01", first),
+        notify("0x01", first),
+        reply("0x02", first),
+        notify("0x01", moved),
+    ] {
+        sender
+            .send_to(packet.as_bytes(), listener_addr)
+            .await
+            .unwrap();
+    }
+
+    match events.recv().await.unwrap() {
+        RegistryEvent::Added(bulb) => assert_eq!(bulb.addr(), first),
+        event => panic!("Unexpected event: {event:?}"),
+    }
+    // The repeated notification and the search reply cause no events.
+    match events.recv().await.unwrap() {
+        RegistryEvent::Changed(bulb) => assert_eq!(bulb.addr(), moved),
+        event => panic!("Unexpected event: {event:?}"),
+    }
+
+    let registry = registry.lock().unwrap();
+    assert_eq!(registry.entries().count(), 1);
+    assert_eq!(registry.get("0x01").unwrap().bulb.addr(), moved);
+}
@@ -4,29 +4,41 @@ use tokio::io::BufReader;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
 
-use yeetlight::Response;
+use serde_json::{json, Value};
+use yeetlight::{Bulb, BulbError, Response};
 
 pub struct BulbConnection {
-    connection: TcpStream,
+    connection: BufReader<TcpStream>,
     last_command_id: u16,
 }
 
 impl BulbConnection {
     fn new(connection: TcpStream) -> io::Result<Self> {
         Ok(Self {
-            connection,
+            connection: BufReader::new(connection),
             last_command_id: 0,
         })
     }
 
-    pub async fn receive(&mut self) -> io::Result<String> {
+    /// Connect to the client, as the bulbs do in the music mode.
+    pub async fn connect(addr: SocketAddr) -> Result<Self, BulbError> {
+        Ok(Self::new(TcpStream::connect(addr).await?)?)
+    }
+
+    pub async fn receive(&mut self) -> Result<String, BulbError> {
         self.receive_and_respond(Response::default()).await
     }
 
-    pub async fn receive_and_respond(&mut self, response: Response) -> io::Result<String> {
-        let mut reader = BufReader::new(&mut self.connection);
+    /// Receive a message and never respond to it.
+    pub async fn receive_and_ignore(&mut self) -> Result<String, BulbError> {
+        let mut message = String::new();
+        self.connection.read_line(&mut message).await?;
+        Ok(message.trim_end().to_owned())
+    }
+
+    pub async fn receive_and_respond(&mut self, response: Response) -> Result<String, BulbError> {
         let mut message = String::new();
-        reader.read_line(&mut message).await?;
+        self.connection.read_line(&mut message).await?;
         let message = message.trim_end();
 
         self.last_command_id += 1;
@@ -34,13 +46,37 @@ impl BulbConnection {
             id: self.last_command_id,
             ..response
         };
+        self.respond(response).await?;
 
+        Ok(message.to_owned())
+    }
+
+    /// Send a response as is, without matching it to any message.
+    pub async fn respond(&mut self, response: Response) -> Result<(), BulbError> {
         let payload = serde_json::to_string(&response)?;
         let payload = payload + "\r\n";
 
         self.connection.write_all(payload.as_bytes()).await?;
+        Ok(())
+    }
 
-        Ok(message.to_owned())
+    /// Send a raw line, e.g. a garbled one.
+    pub async fn send_line(&mut self, line: &str) -> Result<(), BulbError> {
+        let payload = line.to_owned() + "\r\n";
+        self.connection.write_all(payload.as_bytes()).await?;
+        Ok(())
+    }
+
+    /// Push a notification about the changed props.
+    pub async fn notify(&mut self, params: Value) -> Result<(), BulbError> {
+        self.notify_method("props", params).await
+    }
+
+    pub async fn notify_method(&mut self, method: &str, params: Value) -> Result<(), BulbError> {
+        let notification = json!({"method": method, "params": params});
+        let payload = notification.to_string() + "\r\n";
+        self.connection.write_all(payload.as_bytes()).await?;
+        Ok(())
     }
 }
 
@@ -50,14 +86,34 @@ pub struct BulbListener {
 }
 
 impl BulbListener {
+    /// Listen on a random port, so that the tests can run in parallel.
     pub async fn serve(ip_addr: IpAddr) -> io::Result<Self> {
-        let addr = SocketAddr::new(ip_addr, yeetlight::bulb::PORT);
-        let listener = tokio::net::TcpListener::bind(addr).await?;
+        let listener = tokio::net::TcpListener::bind(SocketAddr::new(ip_addr, 0)).await?;
+        let addr = listener.local_addr()?;
         Ok(BulbListener { listener, addr })
     }
 
-    pub async fn accept(&self) -> io::Result<BulbConnection> {
+    pub async fn accept(&self) -> Result<BulbConnection, BulbError> {
         let (connection, _) = self.listener.accept().await?;
-        BulbConnection::new(connection)
+        Ok(BulbConnection::new(connection)?)
     }
 }
+
+/// Serve a mock bulb and connect to it.
+pub async fn connect_pair() -> (BulbListener, BulbConnection, yeetlight::BulbConnection) {
+    connect_pair_with(|bulb| bulb).await
+}
+
+/// Serve a mock bulb and connect to it, after `configure` adjusts
+/// the bulb, e.g. its timeouts.
+pub async fn connect_pair_with(
+    configure: impl FnOnce(Bulb) -> Bulb,
+) -> (BulbListener, BulbConnection, yeetlight::BulbConnection) {
+    let mock_listener = BulbListener::serve("127.0.0.1".parse().unwrap())
+        .await
+        .unwrap();
+    let bulb = configure(Bulb::from(mock_listener.addr));
+    let (mock_connection, bulb_connection) =
+        tokio::try_join!(mock_listener.accept(), bulb.connect()).unwrap();
+    (mock_listener, mock_connection, bulb_connection)
+}